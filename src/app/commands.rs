@@ -0,0 +1,52 @@
+use std::{
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use super::{App, SystemAccess, SystemLocals, SystemParameter};
+
+type Command = Box<dyn FnOnce(&mut App) + Send>;
+
+/// Commands queued by systems, applied in order by [`App::update`] at the end of every stage.
+#[derive(Default, Clone)]
+pub(super) struct CommandQueue {
+    commands: Arc<Mutex<Vec<Command>>>,
+}
+
+impl CommandQueue {
+    pub(super) fn apply(&self, app: &mut App) {
+        let commands = mem::take(&mut *self.commands.lock().unwrap());
+
+        for command in commands {
+            command(app);
+        }
+    }
+}
+
+/// Defers changes that can't be made while systems borrow the resources involved, like adding
+/// or removing scene objects while the scene is being iterated. Queued commands run with
+/// exclusive access to the app once the current stage finishes.
+pub struct Commands {
+    queue: CommandQueue,
+}
+
+impl Commands {
+    pub fn add<C>(&self, command: C)
+    where
+        C: FnOnce(&mut App) + Send + 'static,
+    {
+        self.queue.commands.lock().unwrap().push(Box::new(command));
+    }
+}
+
+impl SystemParameter for Commands {
+    type BorrowedFromApp = Commands;
+
+    fn get_from_app(app: &App, _locals: &SystemLocals) -> Self::BorrowedFromApp {
+        Commands {
+            queue: app.command_queue.clone(),
+        }
+    }
+
+    fn access(_access: &mut SystemAccess) {}
+}
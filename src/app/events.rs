@@ -0,0 +1,123 @@
+use std::{
+    any::type_name,
+    mem,
+    slice::Iter,
+    sync::{RwLockReadGuard, RwLockWriteGuard},
+};
+
+use super::{App, Res, ResMut, SystemAccess, SystemLocals, SystemParameter};
+
+/// Double buffered queue of events. Events sent during a frame can be read for the whole next
+/// frame, regardless of the order systems run in, and are dropped after that.
+pub struct Events<T> {
+    previous_frame_events: Vec<T>,
+    current_frame_events: Vec<T>,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            previous_frame_events: vec![],
+            current_frame_events: vec![],
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.current_frame_events.push(event);
+    }
+
+    /// Events sent during the previous frame.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.previous_frame_events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous_frame_events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.previous_frame_events.is_empty()
+    }
+
+    /// Swaps the buffers, called by [`App::update`] at the start of every frame.
+    pub fn update(&mut self) {
+        mem::swap(
+            &mut self.previous_frame_events,
+            &mut self.current_frame_events,
+        );
+        self.current_frame_events.clear();
+    }
+}
+
+pub(super) fn update_events<T>(app: &App)
+where
+    T: 'static,
+{
+    app.get_resource_mut::<Events<T>>()
+        .expect(&format!(
+            "failed getting events: '{}' from world",
+            type_name::<T>()
+        ))
+        .get_mut()
+        .update();
+}
+
+pub struct EventWriter<T> {
+    events: ResMut<Events<T>>,
+}
+
+impl<T> EventWriter<T> {
+    pub fn send(&self, event: T) {
+        self.events.get_mut().send(event);
+    }
+
+    pub fn get_mut(&self) -> RwLockWriteGuard<'_, Events<T>> {
+        self.events.get_mut()
+    }
+}
+
+impl<T> SystemParameter for EventWriter<T>
+where
+    T: 'static,
+{
+    type BorrowedFromApp = EventWriter<T>;
+
+    fn get_from_app(app: &App, locals: &SystemLocals) -> Self::BorrowedFromApp {
+        EventWriter {
+            events: ResMut::<Events<T>>::get_from_app(app, locals),
+        }
+    }
+
+    fn access(access: &mut SystemAccess) {
+        access.add_write::<Events<T>>();
+    }
+}
+
+pub struct EventReader<T> {
+    events: Res<Events<T>>,
+}
+
+impl<T> EventReader<T> {
+    pub fn get(&self) -> RwLockReadGuard<'_, Events<T>> {
+        self.events.get()
+    }
+}
+
+impl<T> SystemParameter for EventReader<T>
+where
+    T: 'static,
+{
+    type BorrowedFromApp = EventReader<T>;
+
+    fn get_from_app(app: &App, locals: &SystemLocals) -> Self::BorrowedFromApp {
+        EventReader {
+            events: Res::<Events<T>>::get_from_app(app, locals),
+        }
+    }
+
+    fn access(access: &mut SystemAccess) {
+        access.add_read::<Events<T>>();
+    }
+}
@@ -1,44 +1,356 @@
 use std::{
     any::{type_name, Any, TypeId},
-    cell::{Ref, RefCell, RefMut},
     collections::HashMap,
+    collections::HashSet,
     marker::PhantomData,
-    mem,
-    rc::Rc,
+    mem::{self, ManuallyDrop},
+    sync::{Arc, Mutex, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    thread::{self, ThreadId},
+    time::Instant,
 };
 
+use self::{commands::CommandQueue, events::Events, time::Time};
+
+pub mod commands;
+pub mod events;
+pub mod time;
+
 #[repr(usize)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Stage {
+    /// Runs once before the first frame.
+    Startup,
+    /// Runs zero or more times per frame, once for every [`Time::fixed_delta`] that passed.
+    FixedUpdate,
     Update,
     RenderSetup,
     RenderStart, //TODO: Replace with some system of barriers
     Render,
     RenderPresent, //TODO: Replace with some system of barriers
     RenderCleanup,
+    /// Runs once from [`App::shutdown`].
+    Shutdown,
+}
+
+/// Name used to order systems against each other within a stage. Every system is implicitly
+/// labeled with its debug label (the path of the system function).
+pub type SystemLabel = &'static str;
+
+/// Label of the systems writing files on [`Stage::Shutdown`], disable it to exit without touching
+/// anything on disk.
+pub const AUTOSAVE_LABEL: SystemLabel = "autosave";
+
+struct SystemDescriptor {
+    system: Box<dyn System>,
+    access: SystemAccess,
+    labels: Vec<SystemLabel>,
+    before: Vec<SystemLabel>,
+    after: Vec<SystemLabel>,
+    run_conditions: Vec<Box<dyn Fn(&App) -> bool + Send + Sync>>,
+    on_main_thread: bool,
+}
+
+impl SystemDescriptor {
+    fn has_label(&self, label: SystemLabel) -> bool {
+        self.system.get_debug_label() == label || self.labels.contains(&label)
+    }
+
+    fn should_run(&self, app: &App) -> bool {
+        let is_enabled = {
+            let system_toggles = app.get_resource::<SystemToggles>().unwrap();
+            let system_toggles = system_toggles.get();
+
+            system_toggles.is_label_enabled(self.system.get_debug_label())
+                && self
+                    .labels
+                    .iter()
+                    .all(|label| system_toggles.is_label_enabled(label))
+        };
+
+        is_enabled
+            && self
+                .run_conditions
+                .iter()
+                .all(|run_condition| run_condition(app))
+    }
+
+    fn conflicts_with(&self, other: &SystemDescriptor) -> bool {
+        self.access.writes.iter().any(|(write_id, _)| {
+            other.access.reads.iter().any(|(id, _)| id == write_id)
+                || other.access.writes.iter().any(|(id, _)| id == write_id)
+        }) || other
+            .access
+            .writes
+            .iter()
+            .any(|(write_id, _)| self.access.reads.iter().any(|(id, _)| id == write_id))
+    }
+}
+
+/// Order of a stage's systems and the systems each one has to wait for.
+#[derive(Clone)]
+struct SystemSchedule {
+    order: Vec<usize>,
+    dependencies: Vec<Vec<usize>>,
+}
+
+/// Systems and stages switched off at runtime. Resource of every [`App`], so systems can flip
+/// whole groups of other systems, like the editor when switching to a game view.
+#[derive(Default)]
+pub struct SystemToggles {
+    disabled_labels: HashSet<SystemLabel>,
+    disabled_stages: HashSet<usize>,
+}
+
+impl SystemToggles {
+    pub fn set_label_enabled(&mut self, label: SystemLabel, is_enabled: bool) {
+        if is_enabled {
+            self.disabled_labels.remove(label);
+        } else {
+            self.disabled_labels.insert(label);
+        }
+    }
+
+    pub fn is_label_enabled(&self, label: SystemLabel) -> bool {
+        !self.disabled_labels.contains(label)
+    }
+
+    pub fn set_stage_enabled(&mut self, stage: Stage, is_enabled: bool) {
+        if is_enabled {
+            self.disabled_stages.remove(&(stage as usize));
+        } else {
+            self.disabled_stages.insert(stage as usize);
+        }
+    }
+
+    pub fn is_stage_enabled(&self, stage: Stage) -> bool {
+        !self.disabled_stages.contains(&(stage as usize))
+    }
+}
+
+/// Returned by [`App::add_system`] to label the system and declare its ordering constraints.
+pub struct SystemConfig<'app> {
+    descriptor: &'app mut SystemDescriptor,
+}
+
+impl<'app> SystemConfig<'app> {
+    pub fn label(self, label: SystemLabel) -> Self {
+        self.descriptor.labels.push(label);
+        self
+    }
+
+    /// Runs this system before every system with the given label.
+    pub fn before(self, label: SystemLabel) -> Self {
+        self.descriptor.before.push(label);
+        self
+    }
+
+    /// Runs this system after every system with the given label.
+    pub fn after(self, label: SystemLabel) -> Self {
+        self.descriptor.after.push(label);
+        self
+    }
+
+    /// Only runs this system on frames the condition returns true. Skipped runs still show up
+    /// in the profiler. Conditions are checked before the system's batch of parallel systems
+    /// starts, order the system after whatever the condition depends on.
+    pub fn run_if<C>(self, run_condition: C) -> Self
+    where
+        C: Fn(&App) -> bool + Send + Sync + 'static,
+    {
+        self.descriptor.run_conditions.push(Box::new(run_condition));
+        self
+    }
+
+    /// Runs this system on the thread that updates the app, like the systems borrowing non send
+    /// resources. Needed for systems that open OS dialogs, which some platforms only allow on
+    /// the main thread.
+    pub fn on_main_thread(self) -> Self {
+        self.descriptor.on_main_thread = true;
+        self
+    }
+}
+
+/// Bundles the resources and systems of a subsystem so it can be added to an [`App`] as a whole.
+pub trait Plugin {
+    fn build(&self, app: &mut App);
+}
+
+/// Storage of a single resource. [`Res`] and [`ResMut`] of resources that aren't `Send` and `Sync`
+/// aren't `Send` either, so they stay on the thread they were handed out on.
+struct ResourceCell<T> {
+    lock: RwLock<T>,
+}
+
+impl<T> ResourceCell<T> {
+    fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.lock
+            .try_read()
+            .expect(&format!("borrow error resource: '{}'", type_name::<T>()))
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.lock
+            .try_write()
+            .expect(&format!("borrow error resource: '{}'", type_name::<T>()))
+    }
+}
+
+/// Resources that aren't `Send` and `Sync`, pinned to the thread that created the app. They are
+/// only handed out, replaced and dropped on that thread, the scheduler runs every system
+/// borrowing them on it.
+struct NonSendResources {
+    /// `Arc<ResourceCell<R>>` of every resource, only dropped after checking the thread.
+    cells: ManuallyDrop<HashMap<TypeId, Box<dyn Any>>>,
+    owner_thread_id: ThreadId,
+}
+
+// Safety: the cells are never touched outside of the owner thread, every method that reaches them
+// checks it and so does dropping.
+unsafe impl Send for NonSendResources {}
+unsafe impl Sync for NonSendResources {}
+
+impl NonSendResources {
+    fn new() -> Self {
+        Self {
+            cells: Default::default(),
+            owner_thread_id: thread::current().id(),
+        }
+    }
+
+    fn check_thread(&self) {
+        assert!(
+            self.owner_thread_id == thread::current().id(),
+            "non send resources used outside of the thread that created the app"
+        );
+    }
+
+    fn contains(&self, type_id: &TypeId) -> bool {
+        self.cells.contains_key(type_id)
+    }
+
+    fn get(&self, type_id: &TypeId) -> Option<&dyn Any> {
+        let cell = self.cells.get(type_id)?;
+        self.check_thread();
+
+        Some(cell.as_ref())
+    }
+
+    fn insert(&mut self, type_id: TypeId, cell: Box<dyn Any>) {
+        self.check_thread();
+
+        self.cells.insert(type_id, cell);
+    }
+
+    fn remove(&mut self, type_id: &TypeId) -> Option<Box<dyn Any>> {
+        if !self.contains(type_id) {
+            return None;
+        }
+
+        self.check_thread();
+
+        self.cells.remove(type_id)
+    }
+}
+
+impl Drop for NonSendResources {
+    fn drop(&mut self) {
+        // Panics before dropping anything, the resources are leaked instead
+        if !self.cells.is_empty() {
+            self.check_thread();
+        }
+
+        unsafe { ManuallyDrop::drop(&mut self.cells) };
+    }
 }
 
 pub struct App {
-    resources: HashMap<TypeId, Rc<dyn Any>>,
-    stage_system_groups: Vec<Vec<Rc<dyn System>>>,
+    /// `Arc<ResourceCell<R>>` of every resource that is `Send` and `Sync`.
+    resources: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    non_send_resources: NonSendResources,
+    stage_system_groups: Vec<Vec<SystemDescriptor>>,
+    stage_system_schedules: Vec<Option<SystemSchedule>>,
+    event_updaters: Vec<fn(&App)>,
+    command_queue: CommandQueue,
+    has_started: bool,
 }
 
 impl Default for App {
     fn default() -> Self {
-        Self {
+        let stage_count = mem::variant_count::<Stage>();
+
+        let mut app = Self {
             resources: Default::default(),
-            stage_system_groups: vec![vec![]; mem::variant_count::<Stage>()],
-        }
+            non_send_resources: NonSendResources::new(),
+            stage_system_groups: (0..stage_count).map(|_| vec![]).collect(),
+            stage_system_schedules: vec![None; stage_count],
+            event_updaters: vec![],
+            command_queue: Default::default(),
+            has_started: false,
+        };
+
+        app.add_resource(Time::default());
+        app.add_resource(SystemToggles::default());
+
+        app
     }
 }
 
 impl App {
     pub fn add_resource<R>(&mut self, resource: R)
+    where
+        R: Send + Sync + 'static,
+    {
+        self.non_send_resources.remove(&TypeId::of::<R>());
+
+        self.resources.insert(
+            TypeId::of::<R>(),
+            Box::new(Arc::new(ResourceCell {
+                lock: RwLock::new(resource),
+            })),
+        );
+    }
+
+    /// Adds the resource with its default value unless the app has it already, for resources
+    /// shared by several plugins.
+    pub fn init_resource<R>(&mut self)
+    where
+        R: Default + Send + Sync + 'static,
+    {
+        if !self.contains_resource::<R>() {
+            self.add_resource(R::default());
+        }
+    }
+
+    /// Adds a resource that can only be borrowed on the thread that created the app, systems
+    /// using it always run on that thread. Meant for things like the window and the egui winit
+    /// state.
+    pub fn add_non_send_resource<R>(&mut self, resource: R)
+    where
+        R: 'static,
+    {
+        self.resources.remove(&TypeId::of::<R>());
+
+        self.non_send_resources.insert(
+            TypeId::of::<R>(),
+            Box::new(Arc::new(ResourceCell {
+                lock: RwLock::new(resource),
+            })),
+        );
+    }
+
+    fn get_resource_cell<R>(&self) -> Option<Arc<ResourceCell<R>>>
     where
         R: 'static,
     {
-        self.resources
-            .insert(TypeId::of::<R>(), Rc::new(RefCell::new(resource)));
+        let cell = match self.resources.get(&TypeId::of::<R>()) {
+            Some(cell) => cell.downcast_ref::<Arc<ResourceCell<R>>>(),
+            None => self
+                .non_send_resources
+                .get(&TypeId::of::<R>())?
+                .downcast_ref::<Arc<ResourceCell<R>>>(),
+        };
+
+        Some(cell.unwrap().clone())
     }
 
     pub fn get_resource<R>(&self) -> Option<Res<R>>
@@ -46,12 +358,7 @@ impl App {
         R: 'static,
     {
         Some(Res {
-            rc: self
-                .resources
-                .get(&TypeId::of::<R>())?
-                .clone()
-                .downcast::<RefCell<R>>()
-                .unwrap(),
+            cell: self.get_resource_cell::<R>()?,
         })
     }
 
@@ -60,67 +367,482 @@ impl App {
         R: 'static,
     {
         Some(ResMut {
-            rc: self
-                .resources
-                .get(&TypeId::of::<R>())?
-                .clone()
-                .downcast::<RefCell<R>>()
-                .unwrap(),
+            cell: self.get_resource_cell::<R>()?,
         })
     }
 
-    pub fn add_system<S, I>(&mut self, stage: Stage, system: S)
+    pub fn contains_resource<R>(&self) -> bool
+    where
+        R: 'static,
+    {
+        self.resources.contains_key(&TypeId::of::<R>())
+            || self.non_send_resources.contains(&TypeId::of::<R>())
+    }
+
+    /// Removes the resource from the app and returns it. Returns `None` and leaves the resource in
+    /// place if it's still held by clones of [`Res`]/[`ResMut`] outside the app.
+    pub fn remove_resource<R>(&mut self) -> Option<R>
+    where
+        R: 'static,
+    {
+        // One reference is held by the app and one by the cell returned here
+        if Arc::strong_count(&self.get_resource_cell::<R>()?) > 2 {
+            return None;
+        }
+
+        let cell: Box<dyn Any> = match self.resources.remove(&TypeId::of::<R>()) {
+            Some(cell) => cell,
+            None => self.non_send_resources.remove(&TypeId::of::<R>())?,
+        };
+        let cell = *cell.downcast::<Arc<ResourceCell<R>>>().unwrap();
+
+        Arc::try_unwrap(cell)
+            .ok()
+            .map(|cell| cell.lock.into_inner().unwrap())
+    }
+
+    pub fn add_plugin<P>(&mut self, plugin: P) -> &mut Self
     where
-        S: Fn<I, Output = ()> + 'static,
+        P: Plugin,
+    {
+        plugin.build(self);
+        self
+    }
+
+    /// Adds an [`Events<E>`] resource which gets its buffers swapped at the start of every frame.
+    pub fn add_event<E>(&mut self)
+    where
+        E: Send + Sync + 'static,
+    {
+        if self.resources.contains_key(&TypeId::of::<Events<E>>()) {
+            return;
+        }
+
+        self.add_resource(Events::<E>::default());
+        self.event_updaters.push(events::update_events::<E>);
+    }
+
+    pub fn add_system<S, I>(&mut self, stage: Stage, system: S) -> SystemConfig<'_>
+    where
+        S: Fn<I, Output = ()> + Send + Sync + 'static,
         I: std::marker::Tuple + 'static,
         SystemWrapper<S, I>: System,
     {
         let stage_index = stage as usize;
 
-        self.stage_system_groups[stage_index].push(Rc::new(SystemWrapper {
+        let system = SystemWrapper {
             system,
+            locals: Default::default(),
             _pd: PhantomData,
-        }))
+        };
+
+        let access = system.access();
+
+        if let Some(conflict) = access.find_conflict() {
+            panic!(
+                "system '{}' can't be added: {}",
+                system.get_debug_label(),
+                conflict
+            );
+        }
+
+        self.stage_system_schedules[stage_index] = None;
+
+        let system_group = &mut self.stage_system_groups[stage_index];
+        system_group.push(SystemDescriptor {
+            system: Box::new(system),
+            access,
+            labels: vec![],
+            before: vec![],
+            after: vec![],
+            run_conditions: vec![],
+            on_main_thread: false,
+        });
+
+        SystemConfig {
+            descriptor: system_group.last_mut().unwrap(),
+        }
+    }
+
+    /// Runs one frame. The first call also runs the [`Stage::Startup`] systems before it.
+    pub fn update(&mut self) {
+        self.update_at(Instant::now());
+    }
+
+    /// Runs `frames` frames and then the [`Stage::Shutdown`] systems, without a window or an event
+    /// loop. Time doesn't follow the clock, every frame after the first one advances it by exactly
+    /// one fixed step so runs are reproducible. Plugins that need a window can't be added.
+    pub fn run_headless(&mut self, frames: u32) {
+        let fixed_delta = self.get_resource::<Time>().unwrap().get().fixed_delta();
+        let start = Instant::now();
+
+        for frame in 0..frames {
+            self.update_at(start + fixed_delta * frame);
+        }
+
+        self.shutdown();
+    }
+
+    fn update_at(&mut self, now: Instant) {
+        self.sort_system_groups();
+
+        if !self.has_started {
+            self.has_started = true;
+            self.execute_stage(Stage::Startup);
+        }
+
+        for update_events in &self.event_updaters {
+            update_events(self);
+        }
+
+        let fixed_steps = {
+            let time = self.get_resource_mut::<Time>().unwrap();
+            let mut time = time.get_mut();
+
+            time.update(now);
+            time.take_fixed_steps()
+        };
+
+        for _ in 0..fixed_steps {
+            self.execute_stage(Stage::FixedUpdate);
+        }
+
+        for i in Stage::Update as usize..=Stage::RenderCleanup as usize {
+            self.execute_stage(unsafe { mem::transmute::<usize, Stage>(i) });
+        }
+    }
+
+    /// Runs the [`Stage::Shutdown`] systems, should be called once when the app is exiting.
+    pub fn shutdown(&mut self) {
+        self.sort_system_groups();
+
+        self.execute_stage(Stage::Shutdown);
+    }
+
+    fn sort_system_groups(&mut self) {
+        for i in 0..self.stage_system_groups.len() {
+            if self.stage_system_schedules[i].is_none() {
+                let stage = unsafe { mem::transmute::<usize, Stage>(i) };
+
+                let schedule =
+                    sort_system_group(&self.stage_system_groups[i]).unwrap_or_else(|cycle| {
+                        panic!(
+                            "cycle in {:?} system ordering: {}",
+                            stage,
+                            cycle.join(" -> ")
+                        )
+                    });
+
+                self.stage_system_schedules[i] = Some(schedule);
+            }
+        }
+    }
+
+    pub fn set_label_enabled(&mut self, label: SystemLabel, is_enabled: bool) {
+        self.get_resource_mut::<SystemToggles>()
+            .unwrap()
+            .get_mut()
+            .set_label_enabled(label, is_enabled);
+    }
+
+    pub fn set_stage_enabled(&mut self, stage: Stage, is_enabled: bool) {
+        self.get_resource_mut::<SystemToggles>()
+            .unwrap()
+            .get_mut()
+            .set_stage_enabled(stage, is_enabled);
+    }
+
+    fn execute_stage(&mut self, stage: Stage) {
+        self.execute_stage_systems(stage);
+
+        self.command_queue.clone().apply(self);
     }
 
-    pub fn execute(&mut self) {
-        for (i, system_group) in self.stage_system_groups.iter().enumerate() {
-            let system_group_name = unsafe { format!("{:?}", mem::transmute::<usize, Stage>(i)) };
+    fn execute_stage_systems(&self, stage: Stage) {
+        let system_group_name = format!("{:?}", stage);
+
+        let is_stage_enabled = self
+            .get_resource::<SystemToggles>()
+            .unwrap()
+            .get()
+            .is_stage_enabled(stage);
+
+        if !is_stage_enabled {
+            puffin_egui::puffin::profile_scope!("skipped stage", system_group_name);
+            return;
+        }
+
+        puffin_egui::puffin::profile_function!(system_group_name);
 
-            puffin_egui::puffin::profile_function!(system_group_name);
+        let system_group = &self.stage_system_groups[stage as usize];
+        let schedule = self.stage_system_schedules[stage as usize]
+            .as_ref()
+            .unwrap();
 
-            for system in system_group {
-                puffin_egui::puffin::profile_scope!(system.get_debug_label());
+        // Systems are batched in sorted order. A batch grows until the next system conflicts
+        // with, or has to run after, one of the systems already in it.
+        let mut batch: Vec<usize> = vec![];
 
-                system.execute(&self);
+        for system_index in &schedule.order {
+            let descriptor = &system_group[*system_index];
+
+            let must_wait = batch.iter().any(|&batched_index| {
+                schedule.dependencies[*system_index].contains(&batched_index)
+                    || descriptor.conflicts_with(&system_group[batched_index])
+            });
+
+            if must_wait {
+                self.execute_system_batch(system_group, &batch);
+                batch.clear();
             }
+
+            if !descriptor.should_run(self) {
+                puffin_egui::puffin::profile_scope!(descriptor.system.get_debug_label(), "skipped");
+                continue;
+            }
+
+            batch.push(*system_index);
         }
+
+        self.execute_system_batch(system_group, &batch);
+    }
+
+    /// Runs non conflicting systems on the thread pool. Systems borrowing non send resources or
+    /// pinned with [`SystemConfig::on_main_thread`] run on the calling thread in the meantime.
+    fn execute_system_batch(&self, system_group: &[SystemDescriptor], batch: &[usize]) {
+        if let [system_index] = batch {
+            let system = &system_group[*system_index].system;
+
+            puffin_egui::puffin::profile_scope!(system.get_debug_label());
+
+            system.execute(self);
+
+            return;
+        }
+
+        let (pinned, parallel): (Vec<_>, Vec<_>) = batch
+            .iter()
+            .map(|&i| &system_group[i])
+            .partition(|descriptor| {
+                descriptor.on_main_thread
+                    || descriptor
+                        .access
+                        .reads
+                        .iter()
+                        .chain(descriptor.access.writes.iter())
+                        .any(|(id, _)| self.non_send_resources.contains(id))
+            });
+
+        rayon::in_place_scope(|scope| {
+            for descriptor in parallel {
+                scope.spawn(move |_| {
+                    puffin_egui::puffin::profile_scope!(descriptor.system.get_debug_label());
+
+                    descriptor.system.execute(self);
+                });
+            }
+
+            for descriptor in pinned {
+                puffin_egui::puffin::profile_scope!(descriptor.system.get_debug_label());
+
+                descriptor.system.execute(self);
+            }
+        });
     }
 }
 
+/// Topologically sorts a stage by its `before`/`after` constraints, keeping registration order
+/// between unconstrained systems. On a cycle returns the debug labels of the systems forming it.
+fn sort_system_group(
+    system_group: &[SystemDescriptor],
+) -> Result<SystemSchedule, Vec<&'static str>> {
+    let system_count = system_group.len();
+    let mut dependencies = vec![vec![]; system_count];
+
+    for (i, system) in system_group.iter().enumerate() {
+        for (j, other) in system_group.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+
+            let i_before_j = system.before.iter().any(|label| other.has_label(*label))
+                || other.after.iter().any(|label| system.has_label(*label));
+
+            if i_before_j && !dependencies[j].contains(&i) {
+                dependencies[j].push(i);
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(system_count);
+    let mut is_sorted = vec![false; system_count];
+
+    while order.len() < system_count {
+        let next = (0..system_count).find(|&i| {
+            !is_sorted[i]
+                && dependencies[i]
+                    .iter()
+                    .all(|&dependency| is_sorted[dependency])
+        });
+
+        match next {
+            Some(i) => {
+                is_sorted[i] = true;
+                order.push(i);
+            }
+            None => {
+                // Every unsorted system waits on another unsorted one, so walking unsorted
+                // dependencies has to revisit a system eventually.
+                let mut path = vec![(0..system_count).find(|&i| !is_sorted[i]).unwrap()];
+
+                loop {
+                    let current = *path.last().unwrap();
+                    let dependency = *dependencies[current]
+                        .iter()
+                        .find(|&&dependency| !is_sorted[dependency])
+                        .unwrap();
+
+                    if let Some(cycle_start) = path.iter().position(|&i| i == dependency) {
+                        let mut cycle = path[cycle_start..].to_vec();
+                        cycle.push(dependency);
+                        cycle.reverse();
+
+                        return Err(cycle
+                            .into_iter()
+                            .map(|i| system_group[i].system.get_debug_label())
+                            .collect());
+                    }
+
+                    path.push(dependency);
+                }
+            }
+        }
+    }
+
+    Ok(SystemSchedule {
+        order,
+        dependencies,
+    })
+}
+
 pub trait SystemParameter {
     type BorrowedFromApp;
 
-    fn get_from_app(app: &App) -> Self::BorrowedFromApp;
+    fn get_from_app(app: &App, locals: &SystemLocals) -> Self::BorrowedFromApp;
+
+    /// Records the resources the parameter borrows.
+    fn access(access: &mut SystemAccess);
+}
+
+/// Resources a system borrows immutably and mutably, collected from its parameters.
+#[derive(Debug, Default)]
+pub struct SystemAccess {
+    reads: Vec<(TypeId, &'static str)>,
+    writes: Vec<(TypeId, &'static str)>,
+}
+
+impl SystemAccess {
+    pub fn add_read<T>(&mut self)
+    where
+        T: 'static,
+    {
+        self.reads.push((TypeId::of::<T>(), type_name::<T>()));
+    }
+
+    pub fn add_write<T>(&mut self)
+    where
+        T: 'static,
+    {
+        self.writes.push((TypeId::of::<T>(), type_name::<T>()));
+    }
+
+    /// Reports a resource that would be borrowed mutably while it's already borrowed.
+    pub fn find_conflict(&self) -> Option<String> {
+        for (i, (write_id, write_name)) in self.writes.iter().enumerate() {
+            if self.writes[i + 1..].iter().any(|(id, _)| id == write_id) {
+                return Some(format!(
+                    "resource '{}' is borrowed mutably more than once",
+                    write_name
+                ));
+            }
+
+            if self.reads.iter().any(|(id, _)| id == write_id) {
+                return Some(format!(
+                    "resource '{}' is borrowed both mutably and immutably",
+                    write_name
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+/// State owned by a single parameter of a registered system, backs the [`Local`]s of the
+/// parameter. Every parameter has its own, so two `Local<T>` of one system don't share a value.
+#[derive(Default)]
+pub struct SystemLocals {
+    locals: Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+/// Value private to the system instance that takes it, kept across frames. Starts out as
+/// `T::default()`.
+pub struct Local<T> {
+    lock: Arc<RwLock<T>>,
+}
+
+impl<T> Local<T> {
+    pub fn get(&self) -> RwLockReadGuard<'_, T> {
+        self.lock
+            .try_read()
+            .expect(&format!("borrow error local: '{}'", type_name::<T>()))
+    }
+
+    pub fn get_mut(&self) -> RwLockWriteGuard<'_, T> {
+        self.lock
+            .try_write()
+            .expect(&format!("borrow error local: '{}'", type_name::<T>()))
+    }
+}
+
+impl<T> SystemParameter for Local<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    type BorrowedFromApp = Local<T>;
+
+    fn get_from_app(_app: &App, locals: &SystemLocals) -> Self::BorrowedFromApp {
+        let lock = locals
+            .locals
+            .lock()
+            .unwrap()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(RwLock::new(T::default())))
+            .clone();
+
+        Local {
+            lock: lock.downcast::<RwLock<T>>().unwrap(),
+        }
+    }
+
+    fn access(_access: &mut SystemAccess) {}
 }
 
 pub struct Res<T> {
-    rc: Rc<RefCell<T>>,
+    cell: Arc<ResourceCell<T>>,
 }
 
 impl<T> Clone for Res<T> {
     fn clone(&self) -> Self {
         Self {
-            rc: self.rc.clone(),
+            cell: self.cell.clone(),
         }
     }
 }
 
 impl<T> Res<T> {
-    pub fn get(&self) -> Ref<'_, T> {
-        self.rc
-            .try_borrow()
-            .expect(&format!("borrow error resource: '{}'", type_name::<T>()))
+    pub fn get(&self) -> RwLockReadGuard<'_, T> {
+        self.cell.read()
     }
 }
 
@@ -130,41 +852,41 @@ where
 {
     type BorrowedFromApp = Res<T>;
 
-    fn get_from_app(app: &App) -> Self::BorrowedFromApp {
+    fn get_from_app(app: &App, _locals: &SystemLocals) -> Self::BorrowedFromApp {
         app.get_resource::<T>().expect(&format!(
             "failed getting resource: '{}' from world",
             type_name::<T>()
         ))
     }
+
+    fn access(access: &mut SystemAccess) {
+        access.add_read::<T>();
+    }
 }
 
 pub struct ResMut<T> {
-    rc: Rc<RefCell<T>>,
+    cell: Arc<ResourceCell<T>>,
 }
 
 impl<T> Clone for ResMut<T> {
     fn clone(&self) -> Self {
         Self {
-            rc: self.rc.clone(),
+            cell: self.cell.clone(),
         }
     }
 }
 
 impl<T> ResMut<T> {
-    pub fn get(&self) -> Ref<'_, T> {
-        self.rc
-            .try_borrow()
-            .expect(&format!("borrow error resource: '{}'", type_name::<T>()))
+    pub fn get(&self) -> RwLockReadGuard<'_, T> {
+        self.cell.read()
     }
 
-    pub fn get_mut(&self) -> RefMut<'_, T> {
-        self.rc
-            .try_borrow_mut()
-            .expect(&format!("borrow error resource: '{}'", type_name::<T>()))
+    pub fn get_mut(&self) -> RwLockWriteGuard<'_, T> {
+        self.cell.write()
     }
 
     pub fn replace(&self, value: T) -> T {
-        self.rc.replace(value)
+        mem::replace(&mut *self.cell.write(), value)
     }
 }
 
@@ -174,12 +896,48 @@ where
 {
     type BorrowedFromApp = ResMut<T>;
 
-    fn get_from_app(app: &App) -> Self::BorrowedFromApp {
+    fn get_from_app(app: &App, _locals: &SystemLocals) -> Self::BorrowedFromApp {
         app.get_resource_mut::<T>().expect(&format!(
             "failed getting resource {} from world",
             type_name::<T>()
         ))
     }
+
+    fn access(access: &mut SystemAccess) {
+        access.add_write::<T>();
+    }
+}
+
+/// Resolves to `None` instead of panicking when the resource doesn't exist.
+impl<T> SystemParameter for Option<Res<T>>
+where
+    T: 'static,
+{
+    type BorrowedFromApp = Option<Res<T>>;
+
+    fn get_from_app(app: &App, _locals: &SystemLocals) -> Self::BorrowedFromApp {
+        app.get_resource::<T>()
+    }
+
+    fn access(access: &mut SystemAccess) {
+        access.add_read::<T>();
+    }
+}
+
+/// Resolves to `None` instead of panicking when the resource doesn't exist.
+impl<T> SystemParameter for Option<ResMut<T>>
+where
+    T: 'static,
+{
+    type BorrowedFromApp = Option<ResMut<T>>;
+
+    fn get_from_app(app: &App, _locals: &SystemLocals) -> Self::BorrowedFromApp {
+        app.get_resource_mut::<T>()
+    }
+
+    fn access(access: &mut SystemAccess) {
+        access.add_write::<T>();
+    }
 }
 
 pub struct SystemWrapper<S, I>
@@ -188,29 +946,44 @@ where
     I: std::marker::Tuple,
 {
     system: S,
-    _pd: PhantomData<I>,
+    /// Locals of every parameter, in the order of the parameters.
+    locals: OnceLock<Vec<SystemLocals>>,
+    _pd: PhantomData<fn() -> I>,
 }
 
-pub trait System {
+pub trait System: Send + Sync {
     fn execute(&self, app: &App);
 
     fn get_debug_label(&self) -> &'static str;
+
+    fn access(&self) -> SystemAccess;
 }
 
 macro_rules! impl_system_for_system_wrappers {
     ($($T:ident)+) => {
         impl<S, $($T,)+> System for SystemWrapper<S, ($($T,)+)>
         where
-            S: Fn<($($T,)+), Output = ()>,
+            S: Fn<($($T,)+), Output = ()> + Send + Sync,
             $($T: SystemParameter<BorrowedFromApp = $T> + 'static,)+
         {
             fn execute(&self, app: &App) {
-                self.system.call(($($T::get_from_app(app),)+));
+                let mut locals = self
+                    .locals
+                    .get_or_init(|| [$(stringify!($T),)+].map(|_| SystemLocals::default()).into())
+                    .iter();
+
+                self.system.call(($($T::get_from_app(app, locals.next().unwrap()),)+));
             }
 
             fn get_debug_label(&self) -> &'static str {
                 type_name::<S>()
             }
+
+            fn access(&self) -> SystemAccess {
+                let mut access = SystemAccess::default();
+                $($T::access(&mut access);)+
+                access
+            }
         }
     };
 }
@@ -224,3 +997,46 @@ impl_system_for_system_wrappers!(A B C D E F);
 impl_system_for_system_wrappers!(A B C D E F G);
 impl_system_for_system_wrappers!(A B C D E F G H);
 impl_system_for_system_wrappers!(A B C D E F G H I);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Runs {
+        updates: u32,
+        shutdowns: u32,
+        saves: u32,
+    }
+
+    fn count_update(runs: ResMut<Runs>) {
+        runs.get_mut().updates += 1;
+    }
+
+    fn count_shutdown(runs: ResMut<Runs>) {
+        runs.get_mut().shutdowns += 1;
+    }
+
+    fn count_save(runs: ResMut<Runs>) {
+        runs.get_mut().saves += 1;
+    }
+
+    #[test]
+    fn headless_run_shuts_down_without_autosave() {
+        let mut app = App::default();
+        app.init_resource::<Runs>();
+        app.add_system(Stage::Update, count_update);
+        app.add_system(Stage::Shutdown, count_shutdown);
+        app.add_system(Stage::Shutdown, count_save)
+            .label(AUTOSAVE_LABEL);
+        app.set_label_enabled(AUTOSAVE_LABEL, false);
+
+        app.run_headless(3);
+
+        let runs = app.get_resource::<Runs>().unwrap();
+        let runs = runs.get();
+        assert_eq!(runs.updates, 3);
+        assert_eq!(runs.shutdowns, 1);
+        assert_eq!(runs.saves, 0);
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_FIXED_DELTA: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Frame timing, updated by [`App::update`](super::App::update) at the start of every frame.
+pub struct Time {
+    last_update: Option<Instant>,
+    raw_delta: Duration,
+    delta: Duration,
+    elapsed: Duration,
+    frame_index: u64,
+
+    /// Multiplies the real time that passes, scaled time drives both `delta` and fixed steps.
+    pub time_scale: f32,
+    /// Stops scaled time, `delta` is zero and no fixed steps run while paused.
+    pub paused: bool,
+
+    fixed_delta: Duration,
+    fixed_accumulator: Duration,
+    /// Caps the fixed steps run in one frame so a long frame can't make the next one even longer.
+    pub max_fixed_steps_per_frame: u32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            last_update: None,
+            raw_delta: Duration::ZERO,
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            frame_index: 0,
+            time_scale: 1.0,
+            paused: false,
+            fixed_delta: DEFAULT_FIXED_DELTA,
+            fixed_accumulator: Duration::ZERO,
+            max_fixed_steps_per_frame: 8,
+        }
+    }
+}
+
+impl Time {
+    /// Scaled time since the previous frame.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Unscaled time since the previous frame, advances even while paused.
+    pub fn raw_delta(&self) -> Duration {
+        self.raw_delta
+    }
+
+    /// Scaled time since the first frame.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn elapsed_seconds(&self) -> f64 {
+        self.elapsed.as_secs_f64()
+    }
+
+    /// Index of the current frame, the first frame is 0.
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// Time every [`Stage::FixedUpdate`](super::Stage::FixedUpdate) run simulates.
+    pub fn fixed_delta(&self) -> Duration {
+        self.fixed_delta
+    }
+
+    pub fn fixed_delta_seconds(&self) -> f32 {
+        self.fixed_delta.as_secs_f32()
+    }
+
+    pub fn set_fixed_delta(&mut self, fixed_delta: Duration) {
+        assert!(
+            !fixed_delta.is_zero(),
+            "fixed delta must be larger than zero"
+        );
+
+        self.fixed_delta = fixed_delta;
+    }
+
+    pub fn set_fixed_rate(&mut self, steps_per_second: f64) {
+        assert!(
+            steps_per_second.is_finite() && steps_per_second > 0.0,
+            "fixed rate must be a finite number larger than zero, got: {}",
+            steps_per_second
+        );
+
+        let fixed_delta = Duration::try_from_secs_f64(1.0 / steps_per_second)
+            .expect(&format!("fixed rate: {} is too low", steps_per_second));
+
+        self.set_fixed_delta(fixed_delta);
+    }
+
+    /// How far into the next fixed step the frame is, in the range `0.0..1.0`. Useful for
+    /// interpolating between the last two simulated states when rendering.
+    pub fn fixed_overstep_fraction(&self) -> f32 {
+        self.fixed_accumulator.as_secs_f32() / self.fixed_delta.as_secs_f32()
+    }
+
+    pub(super) fn update(&mut self, now: Instant) {
+        if let Some(last_update) = self.last_update {
+            self.raw_delta = now - last_update;
+            self.frame_index += 1;
+        }
+
+        self.last_update = Some(now);
+
+        self.delta = if self.paused {
+            Duration::ZERO
+        } else {
+            self.raw_delta.mul_f32(self.time_scale.max(0.0))
+        };
+
+        self.elapsed += self.delta;
+        self.fixed_accumulator += self.delta;
+    }
+
+    /// Consumes the accumulated time in fixed steps and returns how many steps to run.
+    pub(super) fn take_fixed_steps(&mut self) -> u32 {
+        let mut steps = 0;
+
+        while self.fixed_accumulator >= self.fixed_delta {
+            if steps == self.max_fixed_steps_per_frame {
+                // Drop the backlog, the simulation slows down instead of falling further behind.
+                self.fixed_accumulator = Duration::from_nanos(
+                    (self.fixed_accumulator.as_nanos() % self.fixed_delta.as_nanos()) as u64,
+                );
+                break;
+            }
+
+            self.fixed_accumulator -= self.fixed_delta;
+            steps += 1;
+        }
+
+        steps
+    }
+}
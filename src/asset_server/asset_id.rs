@@ -1,4 +1,4 @@
-use std::{marker::PhantomData, fmt::Display};
+use std::{marker::PhantomData, fmt::Display, hash::{Hash, Hasher}, path::Path};
 
 use serde::{Serialize, Deserialize};
 
@@ -7,6 +7,7 @@ use crate::Id;
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct AssetId<T> {
     id: Id,
+    #[serde(skip)]
     pd: PhantomData<T>,
 }
 
@@ -18,6 +19,27 @@ impl<T> AssetId<T> {
         }
     }
 
+    pub fn from_id(id: Id) -> Self {
+        Self {
+            id,
+            pd: PhantomData,
+        }
+    }
+
+    /// Id derived from the file the asset was imported from and its label within the file, so
+    /// importing the file again gives the same ids. Importers pass the path the source is stored
+    /// with, which is the same for every path leading to the file.
+    pub fn from_source(path: &Path, label: &str) -> Self {
+        let path = path.to_string_lossy();
+
+        Self::from_id(Id::from_u64(fnv1a_hash(&[path.as_bytes(), &[0], label.as_bytes()])))
+    }
+
+    /// Id derived from the content of the asset, so identical assets get the same id.
+    pub fn from_content(content: &[&[u8]]) -> Self {
+        Self::from_id(Id::from_u64(fnv1a_hash(content)))
+    }
+
     pub fn id(&self) -> Id {
         self.id
     }
@@ -28,6 +50,18 @@ impl<T> AssetId<T> {
     };
 }
 
+/// Stable across runs and versions of the standard library, unlike its default hasher.
+pub(super) fn fnv1a_hash(parts: &[&[u8]]) -> u64 {
+    let mut hash = 0xcbf29ce484222325;
+
+    for byte in parts.iter().copied().flatten() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
 impl<T> Display for AssetId<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.id.fmt(f)
@@ -42,6 +76,12 @@ impl<T> PartialEq for AssetId<T> {
 
 impl<T> Eq for AssetId<T> {}
 
+impl<T> Hash for AssetId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
 impl<T> PartialOrd for AssetId<T> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         match self.id.partial_cmp(&other.id) {
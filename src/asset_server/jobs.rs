@@ -0,0 +1,256 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    app::{events::EventWriter, Res, ResMut},
+    Id,
+};
+
+use super::{asset_id::AssetId, read_asset, AssetImported, AssetServer, AssetType};
+
+pub type JobId = usize;
+
+/// Hands the result of a job to the asset server on the main thread. Returns the ids of the
+/// assets it added or replaced.
+pub type JobOutput = Box<dyn FnOnce(&AssetServer) -> Vec<Id> + Send>;
+
+/// Shared between a job and the main thread.
+#[derive(Default)]
+pub struct JobProgress {
+    /// Bits of an `f32`, there is no atomic float.
+    progress: AtomicU32,
+    cancelled: AtomicBool,
+}
+
+impl JobProgress {
+    /// Fraction of the job that is done, in the range `0.0..=1.0`.
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.progress.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, progress: f32) {
+        self.progress
+            .store(progress.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Jobs should check this between steps and return early once it's set, the result of a
+    /// cancelled job is dropped.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobStatus {
+    Running,
+    /// Failed jobs are kept until they are dismissed, so the error can be shown.
+    Failed(String),
+}
+
+pub struct AssetJob {
+    pub name: String,
+    pub status: JobStatus,
+    progress: Arc<JobProgress>,
+    /// `None` once the job finished.
+    thread: Option<JoinHandle<Result<JobOutput, String>>>,
+    /// Assets the job is reading from disk.
+    read_asset_ids: Vec<Id>,
+}
+
+impl AssetJob {
+    pub fn progress(&self) -> f32 {
+        self.progress.get()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.progress.is_cancelled()
+    }
+}
+
+/// Jobs that import or read assets on worker threads. Their output is handed to the asset server
+/// by [`finish_jobs`], finished and cancelled jobs are removed.
+#[derive(Default)]
+pub struct AssetJobs {
+    jobs: BTreeMap<JobId, AssetJob>,
+    next_job_id: JobId,
+}
+
+impl AssetJobs {
+    /// Runs the job on its own thread rather than the thread pool, so a long import doesn't hold
+    /// up the systems.
+    pub fn spawn<F>(&mut self, name: String, job: F) -> JobId
+    where
+        F: FnOnce(&JobProgress) -> Result<JobOutput, String> + Send + 'static,
+    {
+        self.spawn_reading(name, vec![], job)
+    }
+
+    fn spawn_reading<F>(&mut self, name: String, read_asset_ids: Vec<Id>, job: F) -> JobId
+    where
+        F: FnOnce(&JobProgress) -> Result<JobOutput, String> + Send + 'static,
+    {
+        let progress = Arc::new(JobProgress::default());
+
+        let job_progress = progress.clone();
+        let thread = thread::Builder::new()
+            .name(name.clone())
+            .spawn(move || job(&job_progress))
+            .expect(&format!("failed spawning job: '{}'", name));
+
+        let job_id = self.next_job_id;
+        self.next_job_id += 1;
+
+        self.jobs.insert(
+            job_id,
+            AssetJob {
+                name,
+                status: JobStatus::Running,
+                progress,
+                thread: Some(thread),
+                read_asset_ids,
+            },
+        );
+
+        job_id
+    }
+
+    /// Reads the assets that haven't been read from disk yet and aren't being read by another job.
+    /// Returns `None` if there is nothing to read.
+    pub fn read<T>(&mut self, asset_server: &AssetServer, asset_ids: &[AssetId<T>]) -> Option<JobId>
+    where
+        T: AssetType,
+    {
+        let reading_ids = self
+            .jobs
+            .values()
+            .flat_map(|job| &job.read_asset_ids)
+            .copied()
+            .collect::<HashSet<_>>();
+
+        let store = asset_server.assets::<T>();
+        let Some(directory) = store.directory.clone() else {
+            // Every asset of a store that wasn't read from disk is loaded
+            return None;
+        };
+
+        let unread_asset_ids = asset_ids
+            .iter()
+            .copied()
+            .filter(|asset_id| {
+                !reading_ids.contains(&asset_id.id())
+                    && store.indices.get(asset_id).is_some_and(|index| {
+                        store.slots[*index].as_ref().unwrap().asset.get().is_none()
+                    })
+            })
+            .collect::<HashSet<_>>();
+
+        drop(store);
+
+        if unread_asset_ids.is_empty() {
+            return None;
+        }
+
+        let read_asset_ids = unread_asset_ids.iter().map(AssetId::id).collect();
+
+        Some(self.spawn_reading(
+            format!("reading {} assets", unread_asset_ids.len()),
+            read_asset_ids,
+            move |progress| {
+                let mut assets = vec![];
+
+                for (index, asset_id) in unread_asset_ids.iter().enumerate() {
+                    if progress.is_cancelled() {
+                        break;
+                    }
+
+                    assets.push((*asset_id, read_asset(&directory, asset_id)));
+                    progress.set((index + 1) as f32 / unread_asset_ids.len() as f32);
+                }
+
+                Ok(Box::new(move |asset_server: &AssetServer| {
+                    let store = asset_server.assets::<T>();
+
+                    for (asset_id, asset) in assets {
+                        store.set_read(&asset_id, asset);
+                    }
+
+                    vec![]
+                }))
+            },
+        ))
+    }
+
+    pub fn cancel(&self, job_id: JobId) {
+        if let Some(job) = self.jobs.get(&job_id) {
+            job.progress.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes a failed job.
+    pub fn dismiss(&mut self, job_id: JobId) {
+        if self
+            .jobs
+            .get(&job_id)
+            .is_some_and(|job| job.status != JobStatus::Running)
+        {
+            self.jobs.remove(&job_id);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (JobId, &AssetJob)> {
+        self.jobs.iter().map(|(job_id, job)| (*job_id, job))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+}
+
+/// Hands the output of finished jobs to the asset server.
+pub fn finish_jobs(
+    asset_jobs: ResMut<AssetJobs>,
+    asset_server: Res<AssetServer>,
+    asset_imported: EventWriter<AssetImported>,
+) {
+    let mut asset_jobs = asset_jobs.get_mut();
+    let asset_server = asset_server.get();
+
+    asset_jobs.jobs.retain(|_, job| {
+        if !job.thread.as_ref().is_some_and(JoinHandle::is_finished) {
+            return true;
+        }
+
+        let output = job
+            .thread
+            .take()
+            .unwrap()
+            .join()
+            .unwrap_or_else(|_| Err(String::from("job panicked")));
+
+        if job.is_cancelled() {
+            return false;
+        }
+
+        match output {
+            Ok(output) => {
+                for id in output(&asset_server) {
+                    asset_imported.send(AssetImported { id });
+                }
+
+                false
+            }
+            Err(error) => {
+                println!("job: '{}' failed: {}", job.name, error);
+                job.status = JobStatus::Failed(error);
+
+                true
+            }
+        }
+    });
+}
@@ -1,28 +1,268 @@
-use crate::rendering::{
-    material::Material,
-    model::{Mesh, Model},
-    texture::Texture,
+use crate::{
+    app::{events::EventWriter, App, Plugin, Res, ResMut, Stage, SystemToggles, AUTOSAVE_LABEL},
+    cooking::pack::{AssetPack, DEFAULT_PACK_PATH},
+    error::{Error, ErrorLog},
+    file_format::{self, FileFormat, FileFormatError, Migration},
+    importing::ImportSettings,
+    rendering::{
+        material::Material,
+        model::{Mesh, Model},
+        texture::Texture,
+    },
+    Id,
 };
 
-use self::asset_id::AssetId;
-use serde::{Deserialize, Serialize};
+use self::{
+    asset_id::{fnv1a_hash, AssetId},
+    jobs::AssetJobs,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    any::type_name,
-    cell::{Ref, RefCell, RefMut},
+    any::{type_name, Any, TypeId},
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs::{self},
+    io, mem,
     ops::{Deref, DerefMut},
-    path::Path,
-    rc::Rc,
-    slice::Iter,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::SystemTime,
 };
 
 pub mod asset_id;
+pub mod jobs;
+pub mod references;
+
+pub const DEFAULT_DIRECTORY: &'static str = "./assets_server";
+
+/// File all the assets were saved in before they were saved to [`DEFAULT_DIRECTORY`]. It's
+/// imported once if there is no asset directory yet, and left in place.
+pub const LEGACY_PATH: &'static str = "./assets_server.data";
+
+const MANIFEST_FILE_NAME: &'static str = "manifest.data";
+
+const MANIFEST_FORMAT: FileFormat = FileFormat {
+    magic: *b"AMFT",
+    migrations: &[extend_asset_metadata],
+};
+
+const ASSET_MAGIC: [u8; 4] = *b"ASET";
+
+/// Migrations of the asset file layout shared by every asset type, type specific migrations of
+/// [`AssetType::MIGRATIONS`] go after these.
+pub const ASSET_MIGRATIONS: &[Migration] = &[];
+
+fn corrupt(error: bincode::Error) -> FileFormatError {
+    FileFormatError::Corrupt(error.to_string())
+}
+
+/// Version 1 extends the asset metadata with the source, content hash, tags and properties, and
+/// caches the dependencies of every asset in the manifest. For manifests written before they are
+/// unknown until the asset is read.
+fn extend_asset_metadata(payload: Vec<u8>) -> Result<Vec<u8>, FileFormatError> {
+    // Metadata layout of version 1, so later changes to the metadata types don't change what this
+    // migration writes. The sources are only declared for their layout.
+    #[derive(Default, Serialize)]
+    struct AssetMetadata {
+        name: Option<String>,
+        source: Option<AssetSource>,
+        content_hash: Option<u64>,
+        tags: BTreeSet<String>,
+        properties: BTreeMap<String, String>,
+    }
+
+    #[derive(Serialize)]
+    struct AssetSource {
+        path: PathBuf,
+        label: String,
+        import_settings: ImportSettings,
+        imported_at: Option<SystemTime>,
+    }
+
+    #[derive(Serialize)]
+    enum ImportSettings {
+        Gltf(GltfImportSettings),
+        Image,
+    }
+
+    #[derive(Serialize)]
+    struct GltfImportSettings {
+        reflectance: f32,
+    }
+
+    let mut manifest =
+        bincode::deserialize::<BTreeMap<String, Vec<u8>>>(&payload).map_err(corrupt)?;
 
-pub const DEFAULT_PATH: &'static str = "./assets_server.data";
+    for bytes in manifest.values_mut() {
+        // Asset metadata of version 0 only held the name
+        let entries = bincode::deserialize::<Vec<(Id, Option<String>)>>(bytes).map_err(corrupt)?;
+        let entries = entries
+            .into_iter()
+            .map(|(id, name)| {
+                let metadata = AssetMetadata {
+                    name,
+                    ..Default::default()
+                };
+
+                (id, metadata, None::<Vec<Id>>)
+            })
+            .collect::<Vec<_>>();
+
+        *bytes = bincode::serialize(&entries).unwrap();
+    }
+
+    Ok(bincode::serialize(&manifest).unwrap())
+}
+
+pub struct AssetServerPlugin;
+
+impl Plugin for AssetServerPlugin {
+    fn build(&self, app: &mut App) {
+        let mut asset_server = AssetServer::default();
+
+        asset_server.register::<Model>();
+        asset_server.register::<Mesh>();
+        asset_server.register::<Texture>();
+        asset_server.register::<Material>();
+
+        app.add_resource(asset_server);
+        app.add_resource(AssetJobs::default());
+        app.init_resource::<ErrorLog>();
+        app.add_event::<AssetImported>();
+        app.add_event::<AssetRemoved>();
+        app.add_system(Stage::Update, jobs::finish_jobs);
+        app.add_system(Stage::Update, report_errors);
+        app.add_system(Stage::Update, send_removed);
+
+        // Packs are cooked from the asset directory, the assets read from them aren't saved
+        if cfg!(feature = "cooked") {
+            match AssetPack::open(&DEFAULT_PACK_PATH) {
+                Ok(asset_pack) => {
+                    asset_pack.add_to(&app.get_resource::<AssetServer>().unwrap().get());
+                    app.add_resource(asset_pack);
+                }
+                Err(error) => {
+                    let error_log = app.get_resource_mut::<ErrorLog>().unwrap();
+
+                    error_log.get_mut().report(
+                        &format!("failed loading asset pack: '{}'", DEFAULT_PACK_PATH),
+                        error,
+                    );
+                }
+            }
+        } else {
+            app.add_system(Stage::Startup, load);
+            app.add_system(Stage::Shutdown, save).label(AUTOSAVE_LABEL);
+        }
+    }
+}
+
+/// Keeps a manifest that can't be read next to the default one, or disables autosave if it can't
+/// be moved, so it isn't overwritten. Leaves it in place if autosave is disabled already.
+pub fn load(
+    asset_server: ResMut<AssetServer>,
+    error_log: ResMut<ErrorLog>,
+    system_toggles: ResMut<SystemToggles>,
+) {
+    let mut error_log = error_log.get_mut();
+
+    if !Path::new(DEFAULT_DIRECTORY).exists() && Path::new(LEGACY_PATH).exists() {
+        match asset_server.get().import_legacy_file(&LEGACY_PATH) {
+            Ok(()) => println!("imported assets from: '{}'", LEGACY_PATH),
+            Err(error) => error_log.report(
+                &format!("failed importing assets from: '{}'", LEGACY_PATH),
+                error,
+            ),
+        }
+
+        return;
+    }
+
+    let Err(error) = asset_server
+        .get_mut()
+        .read_from_directory(&DEFAULT_DIRECTORY)
+    else {
+        return;
+    };
+
+    // The manifest is only overwritten by autosave
+    if !system_toggles.get().is_label_enabled(AUTOSAVE_LABEL) {
+        error_log.report("failed loading assets", error);
+        return;
+    }
+
+    let manifest_path = Path::new(DEFAULT_DIRECTORY).join(MANIFEST_FILE_NAME);
+    let backup_path = manifest_path.with_extension("data.bak");
+
+    // Otherwise saving on exit would replace it with an empty manifest
+    match fs::rename(manifest_path, &backup_path) {
+        Ok(()) => error_log.report(
+            "failed loading assets",
+            format!(
+                "{}, the manifest is kept as: '{}'",
+                error,
+                backup_path.display()
+            ),
+        ),
+        Err(rename_error) => {
+            system_toggles
+                .get_mut()
+                .set_label_enabled(AUTOSAVE_LABEL, false);
+
+            error_log.report("failed loading assets", error);
+            error_log.report(
+                &format!(
+                    "failed keeping the manifest as: '{}', autosave is disabled",
+                    backup_path.display()
+                ),
+                rename_error,
+            );
+        }
+    }
+}
+
+pub fn save(asset_server: Res<AssetServer>, error_log: ResMut<ErrorLog>) {
+    if let Err(error) = asset_server.get().write_to_directory(&DEFAULT_DIRECTORY) {
+        error_log.get_mut().report("failed saving assets", error);
+    }
+}
+
+/// Moves the errors of assets and manifests that couldn't be read to the error log.
+pub fn report_errors(asset_server: Res<AssetServer>, error_log: ResMut<ErrorLog>) {
+    let errors = asset_server.get().take_errors();
+
+    if !errors.is_empty() {
+        let mut error_log = error_log.get_mut();
+
+        for (action, error) in errors {
+            error_log.report(&action, error);
+        }
+    }
+}
+
+/// Sends the events of the assets removed since the last frame.
+pub fn send_removed(asset_server: Res<AssetServer>, asset_removed: EventWriter<AssetRemoved>) {
+    for id in asset_server.get().take_removed_ids() {
+        asset_removed.send(AssetRemoved { id });
+    }
+}
+
+/// Sent for every asset an import added or replaced. Replaced assets keep their id, so anything
+/// built from the previous version of the asset is stale.
+pub struct AssetImported {
+    pub id: Id,
+}
+
+/// Sent for every asset removed with [`AssetServer::remove`] or
+/// [`AssetServer::collect_garbage`], anything built from it can be dropped.
+pub struct AssetRemoved {
+    pub id: Id,
+}
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Asset<T> {
     id: AssetId<T>,
+    /// Saved in the manifest rather than the asset file.
+    #[serde(skip)]
     pub metadata: AssetMetadata,
     pub asset: T,
 }
@@ -41,15 +281,35 @@ impl<T> DerefMut for Asset<T> {
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct AssetMetadata {
     pub name: Option<String>,
+    /// `None` for assets that weren't imported from a file.
+    pub source: Option<AssetSource>,
+    /// Hash of the asset as it was last saved, `None` until it's saved the first time.
+    pub content_hash: Option<u64>,
+    pub tags: BTreeSet<String>,
+    /// Free-form values for tools, like the author of an asset.
+    pub properties: BTreeMap<String, String>,
+}
+
+/// File an asset was imported from and how, used to re-import it when the file changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetSource {
+    pub path: PathBuf,
+    /// Tells apart the assets imported from the same file, like `mesh0/primitive1`.
+    pub label: String,
+    pub import_settings: ImportSettings,
+    /// `None` for assets imported before import times were recorded.
+    pub imported_at: Option<SystemTime>,
 }
 
 impl<T> Asset<T> {
-    pub fn new(asset: T, mut metadata: AssetMetadata) -> Self {
-        let typed_id = AssetId::<T>::new();
+    pub fn new(asset: T, metadata: AssetMetadata) -> Self {
+        Self::with_id(AssetId::new(), asset, metadata)
+    }
 
+    fn with_id(typed_id: AssetId<T>, asset: T, mut metadata: AssetMetadata) -> Self {
         metadata.name = if let Some(name) = metadata.name {
             Some(name)
         } else {
@@ -72,100 +332,832 @@ impl<T> Asset<T> {
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+struct AssetSlot<T> {
+    id: AssetId<T>,
+    /// Metadata read from the manifest, replaced by [`Asset::metadata`] once the asset is loaded.
+    metadata: AssetMetadata,
+    /// Holds `None` if the asset file couldn't be read.
+    asset: OnceLock<Option<Asset<T>>>,
+    /// Dependencies read from the manifest, used until the asset is loaded. `None` if they weren't
+    /// saved in the manifest.
+    dependencies: Option<Vec<Id>>,
+    /// Changed since it was last written to disk.
+    dirty: bool,
+}
+
+impl<T> AssetSlot<T> {
+    fn metadata(&self) -> &AssetMetadata {
+        match self.asset.get() {
+            Some(Some(asset)) => &asset.metadata,
+            _ => &self.metadata,
+        }
+    }
+}
+
+/// Assets of one type. Lookups by id go through an index, and removed assets leave an empty slot
+/// behind so the remaining ones keep their order and slot. Slots are compacted once more than half
+/// of them are empty.
+///
+/// Stores read from disk only know the ids and metadata of their assets at first, every asset is
+/// read from its own file the first time it's accessed. Assets whose file can't be read are
+/// reported once, see [`AssetServer::take_errors`], and treated as missing.
 pub struct AssetStore<T> {
-    assets: Vec<Asset<T>>,
+    slots: Vec<Option<AssetSlot<T>>>,
+    indices: HashMap<AssetId<T>, usize>,
+    /// Directory the assets not loaded yet are read from.
+    directory: Option<PathBuf>,
+    /// Assets whose files are deleted on the next save.
+    removed_asset_ids: Vec<AssetId<T>>,
+    /// Failed reads not taken by [`AssetServer::take_errors`] yet, the action and the error.
+    errors: Mutex<Vec<(String, String)>>,
 }
 
-impl<T> AssetStore<T> {
+impl<T> AssetStore<T>
+where
+    T: AssetType,
+{
+    fn new() -> Self {
+        Self {
+            slots: vec![],
+            indices: HashMap::new(),
+            directory: None,
+            removed_asset_ids: vec![],
+            errors: Mutex::new(vec![]),
+        }
+    }
+
     pub fn add(&mut self, asset: T, metadata: AssetMetadata) -> &mut Asset<T> {
-        self.assets.push(Asset::new(asset, metadata));
-        self.assets.last_mut().unwrap()
+        let asset = Asset::new(asset, metadata);
+        let asset_id = asset.id();
+
+        self.insert(AssetSlot {
+            id: asset_id,
+            metadata: AssetMetadata::default(),
+            asset: OnceLock::from(Some(asset)),
+            dependencies: None,
+            dirty: true,
+        });
+
+        self.get_mut(&asset_id).unwrap()
     }
 
-    pub fn remove_at_index(&mut self, index: usize) {
-        self.assets.remove(index);
+    /// Adds the asset with an id of its own, like one from [`AssetId::from_source`], replacing
+    /// the asset that already has that id. The tags and properties of the replaced asset are kept.
+    pub fn add_or_replace(&mut self, asset_id: AssetId<T>, asset: T, mut metadata: AssetMetadata) {
+        let Some(index) = self.indices.get(&asset_id) else {
+            self.insert(AssetSlot {
+                id: asset_id,
+                metadata: AssetMetadata::default(),
+                asset: OnceLock::from(Some(Asset::with_id(asset_id, asset, metadata))),
+                dependencies: None,
+                dirty: true,
+            });
+            return;
+        };
+
+        let slot = self.slots[*index].as_mut().unwrap();
+
+        // Set by users, so a re-import doesn't lose them
+        let replaced_metadata = slot.metadata();
+        metadata.tags.extend(replaced_metadata.tags.iter().cloned());
+        for (key, value) in &replaced_metadata.properties {
+            metadata
+                .properties
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+
+        slot.asset = OnceLock::from(Some(Asset::with_id(asset_id, asset, metadata)));
+        slot.dependencies = None;
+        slot.dirty = true;
+    }
+
+    pub fn remove(&mut self, asset_id: &AssetId<T>) -> bool {
+        let Some(index) = self.indices.remove(asset_id) else {
+            return false;
+        };
+
+        self.slots[index] = None;
+        self.removed_asset_ids.push(*asset_id);
+
+        if self.slots.len() > self.indices.len() * 2 {
+            self.compact();
+        }
+
+        true
     }
 
+    /// Reads the asset from disk if it isn't loaded yet.
     pub fn get(&self, asset_id: &AssetId<T>) -> Option<&Asset<T>> {
-        self.assets.iter().find(|asset| asset.id() == *asset_id)
+        let index = *self.indices.get(asset_id)?;
+
+        self.load_slot(self.slots[index].as_ref().unwrap())
+    }
+
+    /// Like [`AssetStore::get`], but tells apart assets that don't exist from ones whose file
+    /// couldn't be read.
+    pub fn load(&self, asset_id: &AssetId<T>) -> Result<&Asset<T>, Error> {
+        let index = *self
+            .indices
+            .get(asset_id)
+            .ok_or(Error::AssetNotFound(asset_id.id()))?;
+
+        self.load_slot(self.slots[index].as_ref().unwrap())
+            .ok_or(Error::AssetUnreadable(asset_id.id()))
+    }
+
+    /// Reads the asset from disk if it isn't loaded yet, and marks it to be written on next save.
+    pub fn get_mut(&mut self, asset_id: &AssetId<T>) -> Option<&mut Asset<T>> {
+        self.get(asset_id)?;
+
+        let index = self.indices[asset_id];
+        let slot = self.slots[index].as_mut().unwrap();
+        slot.dirty = true;
+
+        slot.asset.get_mut().unwrap().as_mut()
     }
 
-    pub fn get_at_index(&self, index: usize) -> Option<&Asset<T>> {
-        self.assets.get(index)
+    pub fn contains(&self, asset_id: &AssetId<T>) -> bool {
+        self.indices.contains_key(asset_id)
+    }
+
+    pub fn is_loaded(&self, asset_id: &AssetId<T>) -> bool {
+        self.indices.get(asset_id).is_some_and(|index| {
+            matches!(
+                self.slots[*index].as_ref().unwrap().asset.get(),
+                Some(Some(_))
+            )
+        })
     }
 
     pub fn len(&self) -> usize {
-        self.assets.len()
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Assets in the order they were added, reads every asset that isn't loaded yet.
+    pub fn iter(&self) -> impl Iterator<Item = &Asset<T>> {
+        self.slots
+            .iter()
+            .flatten()
+            .filter_map(|slot| self.load_slot(slot))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Asset<T>> {
+        for slot in self.slots.iter().flatten() {
+            self.load_slot(slot);
+        }
+
+        self.slots.iter_mut().flatten().filter_map(|slot| {
+            let asset = slot.asset.get_mut().unwrap().as_mut()?;
+            slot.dirty = true;
+
+            Some(asset)
+        })
+    }
+
+    /// Doesn't load the asset.
+    pub fn metadata(&self, asset_id: &AssetId<T>) -> Option<&AssetMetadata> {
+        let index = *self.indices.get(asset_id)?;
+
+        Some(self.slots[index].as_ref().unwrap().metadata())
+    }
+
+    /// Doesn't load the asset or mark it to be written, the metadata is saved with the manifest.
+    pub fn metadata_mut(&mut self, asset_id: &AssetId<T>) -> Option<&mut AssetMetadata> {
+        let index = *self.indices.get(asset_id)?;
+        let slot = self.slots[index].as_mut().unwrap();
+
+        match slot.asset.get_mut() {
+            Some(Some(asset)) => Some(&mut asset.metadata),
+            _ => Some(&mut slot.metadata),
+        }
+    }
+
+    /// First asset with the name, without loading any.
+    pub fn find_by_name(&self, name: &str) -> Option<AssetId<T>> {
+        self.iter_metadata()
+            .find(|(_, metadata)| metadata.name.as_deref() == Some(name))
+            .map(|(asset_id, _)| asset_id)
+    }
+
+    /// Assets with the tag in the order they were added, without loading them.
+    pub fn filter_by_tag<'store>(
+        &'store self,
+        tag: &'store str,
+    ) -> impl Iterator<Item = AssetId<T>> + 'store {
+        self.iter_metadata()
+            .filter(move |(_, metadata)| metadata.tags.contains(tag))
+            .map(|(asset_id, _)| asset_id)
+    }
+
+    /// Ids and metadata of the assets in the order they were added, without loading them.
+    pub fn iter_metadata(&self) -> impl Iterator<Item = (AssetId<T>, &AssetMetadata)> {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|slot| (slot.id, slot.metadata()))
+    }
+
+    fn load_slot<'store>(&'store self, slot: &'store AssetSlot<T>) -> Option<&'store Asset<T>> {
+        let asset = slot.asset.get_or_init(|| {
+            match read_asset(self.directory.as_ref().unwrap(), &slot.id) {
+                Ok(mut asset) => {
+                    asset.metadata = slot.metadata.clone();
+                    Some(asset)
+                }
+                Err(error) => {
+                    self.report_unreadable(&slot.id, error);
+                    None
+                }
+            }
+        });
+
+        asset.as_ref()
+    }
+
+    /// Stores an asset read by a job, unless it was loaded or removed in the meantime.
+    fn set_read(&self, asset_id: &AssetId<T>, asset: Result<Asset<T>, FileFormatError>) {
+        let Some(index) = self.indices.get(asset_id) else {
+            return;
+        };
+
+        let slot = self.slots[*index].as_ref().unwrap();
+
+        if slot.asset.get().is_some() {
+            return;
+        }
+
+        let asset = match asset {
+            Ok(mut asset) => {
+                asset.metadata = slot.metadata.clone();
+                Some(asset)
+            }
+            Err(error) => {
+                self.report_unreadable(asset_id, error);
+                None
+            }
+        };
+
+        let _ = slot.asset.set(asset);
+    }
+
+    fn report_unreadable(&self, asset_id: &AssetId<T>, error: FileFormatError) {
+        let path = asset_path(self.directory.as_ref().unwrap(), asset_id);
+
+        self.errors.lock().unwrap().push((
+            format!("failed loading asset: '{}'", path.display()),
+            error.to_string(),
+        ));
+    }
+
+    /// Reads the asset if it isn't loaded and its dependencies weren't saved in the manifest.
+    fn dependencies(&self, slot: &AssetSlot<T>) -> Vec<Id> {
+        match (slot.asset.get(), &slot.dependencies) {
+            (Some(Some(asset)), _) => asset.dependencies(),
+            (_, Some(dependencies)) => dependencies.clone(),
+            _ => self
+                .load_slot(slot)
+                .map_or(vec![], |asset| asset.dependencies()),
+        }
+    }
+
+    fn insert(&mut self, slot: AssetSlot<T>) {
+        let index = self.slots.len();
+
+        if let Some(old_index) = self.indices.insert(slot.id, index) {
+            self.slots[old_index] = None;
+        }
+
+        self.slots.push(Some(slot));
     }
 
-    pub fn iter(&self) -> Iter<'_, Asset<T>> {
-        self.assets.iter()
+    fn compact(&mut self) {
+        self.slots.retain(Option::is_some);
+
+        for (index, slot) in self.slots.iter().flatten().enumerate() {
+            self.indices.insert(slot.id, index);
+        }
+    }
+
+    fn read_from_manifest(bytes: &[u8], directory: PathBuf) -> Result<Self, FileFormatError> {
+        let manifest_entries =
+            bincode::deserialize::<Vec<(AssetId<T>, AssetMetadata, Option<Vec<Id>>)>>(bytes)
+                .map_err(corrupt)?;
+
+        let mut store = AssetStore::new();
+        store.directory = Some(directory);
+
+        for (asset_id, metadata, dependencies) in manifest_entries {
+            store.insert(AssetSlot {
+                id: asset_id,
+                metadata,
+                asset: OnceLock::new(),
+                dependencies,
+                dirty: false,
+            });
+        }
+
+        Ok(store)
+    }
+
+    /// Writes the dirty assets, deletes the files of removed ones and returns the serialized
+    /// manifest entries.
+    fn write_to_directory(&mut self, directory: &Path) -> io::Result<Vec<u8>> {
+        // Assets that were never loaded only exist in the old directory
+        if self.directory.as_deref() != Some(directory) {
+            for _ in self.iter_mut() {}
+        }
+
+        fs::create_dir_all(directory)?;
+
+        // Before writing, an asset can be added again with the id of a removed one
+        for asset_id in self.removed_asset_ids.drain(..) {
+            let _ = fs::remove_file(asset_path(directory, &asset_id));
+        }
+
+        for slot in self.slots.iter_mut().flatten() {
+            if slot.dirty {
+                let asset = slot.asset.get_mut().unwrap().as_mut().unwrap();
+
+                let path = asset_path(directory, &slot.id);
+
+                file_format::write(&path, &asset_format::<T>(), asset)?;
+                asset.metadata.content_hash =
+                    Some(fnv1a_hash(&[&bincode::serialize(&asset.asset).unwrap()]));
+                slot.dirty = false;
+            }
+        }
+
+        self.directory = Some(directory.to_owned());
+
+        let manifest_entries = self
+            .slots
+            .iter()
+            .flatten()
+            .map(|slot| (slot.id, slot.metadata(), Some(self.dependencies(slot))))
+            .collect::<Vec<_>>();
+
+        Ok(bincode::serialize(&manifest_entries).unwrap())
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+fn asset_path<T>(directory: &Path, asset_id: &AssetId<T>) -> PathBuf {
+    directory.join(format!("{}.data", asset_id))
+}
+
+fn read_asset<T>(directory: &Path, asset_id: &AssetId<T>) -> Result<Asset<T>, FileFormatError>
+where
+    T: AssetType,
+{
+    file_format::read::<Asset<T>, _>(&asset_path(directory, asset_id), &asset_format::<T>())
+}
+
+fn asset_format<T>() -> FileFormat
+where
+    T: AssetType,
+{
+    FileFormat {
+        magic: ASSET_MAGIC,
+        migrations: T::MIGRATIONS,
+    }
+}
+
+/// Asset returned by [`AssetServer::get`], holds the read lock of its store.
+pub struct AssetRef<'store, T> {
+    store: RwLockReadGuard<'store, AssetStore<T>>,
+    asset_id: AssetId<T>,
+}
+
+impl<'store, T> Deref for AssetRef<'store, T>
+where
+    T: AssetType,
+{
+    type Target = Asset<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.store.get(&self.asset_id).unwrap()
+    }
+}
+
+/// Implemented by every type that can be registered with the [`AssetServer`].
+pub trait AssetType: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Key of the store in the manifest and name of the directory of the asset files, so it can't
+    /// change once assets of the type are saved.
+    const NAME: &'static str;
+
+    /// Migrations of the asset files, add one whenever the serialized layout of the type changes.
+    /// Has to start with [`ASSET_MIGRATIONS`], see [`FileFormat::migrations`].
+    const MIGRATIONS: &'static [Migration] = ASSET_MIGRATIONS;
+
+    /// Ids of the assets this one references, like the meshes of a model.
+    fn dependencies(&self) -> Vec<Id> {
+        vec![]
+    }
+}
+
+/// Store of a registered asset type, with the type erased so stores of every type fit in one map.
+trait ErasedAssetStore: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
+    fn write_to_directory(&self, directory: &Path) -> io::Result<Vec<u8>>;
+
+    fn remove(&self, id: Id) -> bool;
+
+    /// Every asset together with the ids of its dependencies.
+    fn dependencies(&self) -> Vec<(Id, Vec<Id>)>;
+
+    fn sources(&self) -> Vec<AssetSource>;
+
+    fn name(&self, id: Id) -> Option<String>;
+
+    fn is_empty(&self) -> bool;
+
+    fn take_errors(&self) -> Vec<(String, String)>;
+}
+
+impl<T> ErasedAssetStore for RwLock<AssetStore<T>>
+where
+    T: AssetType,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn write_to_directory(&self, directory: &Path) -> io::Result<Vec<u8>> {
+        self.write().unwrap().write_to_directory(directory)
+    }
+
+    fn remove(&self, id: Id) -> bool {
+        self.write().unwrap().remove(&AssetId::from_id(id))
+    }
+
+    fn dependencies(&self) -> Vec<(Id, Vec<Id>)> {
+        let store = self.read().unwrap();
+
+        store
+            .slots
+            .iter()
+            .flatten()
+            .map(|slot| (slot.id.id(), store.dependencies(slot)))
+            .collect()
+    }
+
+    fn sources(&self) -> Vec<AssetSource> {
+        self.read()
+            .unwrap()
+            .iter_metadata()
+            .filter_map(|(_, metadata)| metadata.source.clone())
+            .collect()
+    }
+
+    fn name(&self, id: Id) -> Option<String> {
+        self.read()
+            .unwrap()
+            .metadata(&AssetId::from_id(id))?
+            .name
+            .clone()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.read().unwrap().is_empty()
+    }
+
+    fn take_errors(&self) -> Vec<(String, String)> {
+        mem::take(&mut *self.read().unwrap().errors.lock().unwrap())
+    }
+}
+
+fn read_store_from_manifest<T>(
+    bytes: &[u8],
+    directory: PathBuf,
+) -> Result<Box<dyn ErasedAssetStore>, FileFormatError>
+where
+    T: AssetType,
+{
+    let store = AssetStore::<T>::read_from_manifest(bytes, directory)?;
+
+    Ok(Box::new(RwLock::new(store)))
+}
+
+/// Assets of every type are stored in their own directory, see [`AssetType::NAME`].
+fn type_directory(directory: &Path, name: &str) -> PathBuf {
+    directory.join(name)
+}
+
+/// Layout of the stores in the [`LEGACY_PATH`] file, the id, name and asset of every asset.
+type LegacyStore<T> = Vec<(AssetId<T>, Option<String>, T)>;
+
+fn add_legacy_assets<T>(store: &mut AssetStore<T>, assets: LegacyStore<T>)
+where
+    T: AssetType,
+{
+    for (asset_id, name, asset) in assets {
+        let metadata = AssetMetadata {
+            name,
+            ..Default::default()
+        };
+
+        store.add_or_replace(asset_id, asset, metadata);
+    }
+}
+
+struct RegisteredAssetStore {
+    name: &'static str,
+    store: Box<dyn ErasedAssetStore>,
+    read_from_manifest: fn(&[u8], PathBuf) -> Result<Box<dyn ErasedAssetStore>, FileFormatError>,
+}
+
+/// Owns a store for every registered asset type. Every store is locked on its own, so systems can
+/// for example read meshes while the importer adds textures.
+///
+/// On disk every asset has its own file, and a manifest lists the ids and metadata of all the
+/// assets so they can be browsed without reading them.
+#[derive(Default)]
 pub struct AssetServer {
-    models: Rc<RefCell<AssetStore<Model>>>,
-    meshes: Rc<RefCell<AssetStore<Mesh>>>,
-    textures: Rc<RefCell<AssetStore<Texture>>>,
-    materials: Rc<RefCell<AssetStore<Material>>>,
+    stores: HashMap<TypeId, RegisteredAssetStore>,
+    /// Directory the manifest was read from, unregistered types are read from it on registration.
+    directory: Option<PathBuf>,
+    /// Manifest entries read from disk whose type isn't registered yet, keyed by
+    /// [`AssetType::NAME`]. They are read when the type gets registered and written back unchanged
+    /// otherwise.
+    unregistered_manifests: BTreeMap<String, Vec<u8>>,
+    /// Manifest entries of registered types that couldn't be read. The stores of these types
+    /// aren't saved, the entries are written back unchanged instead.
+    unreadable_manifests: BTreeMap<String, Vec<u8>>,
+    /// Assets removed since the last [`AssetServer::take_removed_ids`].
+    removed_ids: Mutex<Vec<Id>>,
 }
 
 impl AssetServer {
-    pub fn read_from_file_or_new<P>(path: &P) -> Self
+    pub fn register<T>(&mut self)
+    where
+        T: AssetType,
+    {
+        if self.stores.contains_key(&TypeId::of::<T>()) {
+            panic!("asset type: '{}' is already registered", T::NAME);
+        }
+
+        let mut store = AssetStore::<T>::new();
+
+        if let Some(bytes) = self.unregistered_manifests.remove(T::NAME) {
+            let directory = type_directory(self.directory.as_ref().unwrap(), T::NAME);
+
+            match AssetStore::<T>::read_from_manifest(&bytes, directory) {
+                Ok(read_store) => store = read_store,
+                Err(error) => {
+                    store.errors.lock().unwrap().push((
+                        format!("failed reading manifest of asset type: '{}'", T::NAME),
+                        error.to_string(),
+                    ));
+
+                    self.unreadable_manifests.insert(T::NAME.to_owned(), bytes);
+                }
+            }
+        }
+
+        let store: Box<dyn ErasedAssetStore> = Box::new(RwLock::new(store));
+
+        self.stores.insert(
+            TypeId::of::<T>(),
+            RegisteredAssetStore {
+                name: T::NAME,
+                store,
+                read_from_manifest: read_store_from_manifest::<T>,
+            },
+        );
+    }
+
+    pub fn is_registered<T>(&self) -> bool
+    where
+        T: AssetType,
+    {
+        self.stores.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Reads the manifest written by [`AssetServer::write_to_directory`], replacing the assets of
+    /// every type it lists. The assets themselves are read when they are first accessed. Does
+    /// nothing if there is no manifest, and leaves the server unchanged if it can't be read.
+    pub fn read_from_directory<P>(&mut self, directory: &P) -> Result<(), Error>
     where
         P: AsRef<Path>,
     {
-        if let Ok(bytes) = fs::read(path) {
-            let decompressed_bytes = lz4_flex::decompress_size_prepended(&bytes).unwrap();
+        let directory = directory.as_ref();
+        let manifest_path = directory.join(MANIFEST_FILE_NAME);
 
-            bincode::deserialize::<Self>(&decompressed_bytes).unwrap()
-        } else {
-            Default::default()
+        let manifest = match file_format::read::<BTreeMap<String, Vec<u8>>, _>(
+            &manifest_path,
+            &MANIFEST_FORMAT,
+        ) {
+            Ok(manifest) => manifest,
+            Err(error) if error.is_not_found() => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut read_stores = vec![];
+        let mut unregistered_manifests = BTreeMap::new();
+
+        for (name, bytes) in manifest {
+            let registered_store = self
+                .stores
+                .iter()
+                .find(|(_, registered_store)| registered_store.name == name);
+
+            match registered_store {
+                Some((type_id, registered_store)) => {
+                    let type_directory = type_directory(directory, &name);
+                    let store = (registered_store.read_from_manifest)(&bytes, type_directory)?;
+
+                    read_stores.push((*type_id, store));
+                }
+                None => {
+                    unregistered_manifests.insert(name, bytes);
+                }
+            }
+        }
+
+        for (type_id, store) in read_stores {
+            let registered_store = self.stores.get_mut(&type_id).unwrap();
+            registered_store.store = store;
+
+            self.unreadable_manifests.remove(registered_store.name);
+        }
+
+        self.unregistered_manifests.extend(unregistered_manifests);
+        self.directory = Some(directory.to_owned());
+
+        Ok(())
+    }
+
+    /// Writes the assets that changed since the last save and the manifest. Stops at the first
+    /// file that can't be written, the manifest is left as it was then. Stores whose manifest
+    /// entry couldn't be read aren't written, saving fails after the rest is written if assets were
+    /// added to one of them.
+    pub fn write_to_directory<P>(&self, directory: &P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let directory = directory.as_ref();
+        let mut manifest = self.unregistered_manifests.clone();
+        manifest.extend(self.unreadable_manifests.clone());
+
+        let mut unsaved_store_name = None;
+
+        for registered_store in self.stores.values() {
+            if self
+                .unreadable_manifests
+                .contains_key(registered_store.name)
+            {
+                if !registered_store.store.is_empty() {
+                    unsaved_store_name.get_or_insert(registered_store.name);
+                }
+
+                continue;
+            }
+
+            let type_directory = type_directory(directory, registered_store.name);
+
+            manifest.insert(
+                registered_store.name.to_owned(),
+                registered_store.store.write_to_directory(&type_directory)?,
+            );
+        }
+
+        file_format::write(
+            &directory.join(MANIFEST_FILE_NAME),
+            &MANIFEST_FORMAT,
+            &manifest,
+        )?;
+
+        match unsaved_store_name {
+            Some(name) => Err(Error::AssetsNotSaved(name)),
+            None => Ok(()),
         }
     }
 
-    pub fn write_to_file<P>(&self, path: &P)
+    /// Adds the assets of the file they were saved in before the asset directory, see
+    /// [`LEGACY_PATH`]. They keep their ids and names, and are written to the directory on the
+    /// next save.
+    pub fn import_legacy_file<P>(&self, path: &P) -> Result<(), Error>
     where
         P: AsRef<Path>,
     {
-        let bytes = bincode::serialize::<Self>(&self).unwrap();
+        let bytes = fs::read(path)?;
+        let payload = lz4_flex::decompress_size_prepended(&bytes)
+            .map_err(|error| FileFormatError::Corrupt(error.to_string()))?;
+
+        let (models, meshes, textures, materials) = bincode::deserialize::<(
+            LegacyStore<Model>,
+            LegacyStore<Mesh>,
+            LegacyStore<Texture>,
+            LegacyStore<Material>,
+        )>(&payload)
+        .map_err(corrupt)?;
 
-        let compressed_bytes = lz4_flex::compress_prepend_size(&bytes);
+        add_legacy_assets(&mut self.assets_mut::<Model>(), models);
+        add_legacy_assets(&mut self.assets_mut::<Mesh>(), meshes);
+        add_legacy_assets(&mut self.assets_mut::<Texture>(), textures);
+        add_legacy_assets(&mut self.assets_mut::<Material>(), materials);
 
-        fs::write(path, compressed_bytes).unwrap();
+        Ok(())
     }
 
-    pub fn models(&self) -> Ref<'_, AssetStore<Model>> {
-        self.models.borrow()
+    pub fn assets<T>(&self) -> RwLockReadGuard<'_, AssetStore<T>>
+    where
+        T: AssetType,
+    {
+        self.store::<T>().read().unwrap()
     }
 
-    pub fn models_mut(&self) -> RefMut<'_, AssetStore<Model>> {
-        self.models.borrow_mut()
+    pub fn assets_mut<T>(&self) -> RwLockWriteGuard<'_, AssetStore<T>>
+    where
+        T: AssetType,
+    {
+        self.store::<T>().write().unwrap()
     }
 
-    pub fn meshes(&self) -> Ref<'_, AssetStore<Mesh>> {
-        self.meshes.borrow()
+    pub fn add<T>(&self, asset: T, metadata: AssetMetadata) -> AssetId<T>
+    where
+        T: AssetType,
+    {
+        self.assets_mut::<T>().add(asset, metadata).id()
     }
 
-    pub fn meshes_mut(&self) -> RefMut<'_, AssetStore<Mesh>> {
-        self.meshes.borrow_mut()
+    /// Keeps the store of `T` locked for reading until the returned reference is dropped.
+    pub fn get<T>(&self, asset_id: &AssetId<T>) -> Option<AssetRef<'_, T>>
+    where
+        T: AssetType,
+    {
+        let store = self.assets::<T>();
+        store.get(asset_id)?;
+
+        Some(AssetRef {
+            store,
+            asset_id: *asset_id,
+        })
     }
 
-    pub fn textures(&self) -> Ref<'_, AssetStore<Texture>> {
-        self.textures.borrow()
+    /// Ids of all the assets of type `T` in the order they were added. The ids are collected up
+    /// front, so the store isn't locked while iterating.
+    pub fn iter<T>(&self) -> std::vec::IntoIter<AssetId<T>>
+    where
+        T: AssetType,
+    {
+        let ids = self
+            .assets::<T>()
+            .iter_metadata()
+            .map(|(asset_id, _)| asset_id)
+            .collect::<Vec<_>>();
+
+        ids.into_iter()
     }
 
-    pub fn textures_mut(&self) -> RefMut<'_, AssetStore<Texture>> {
-        self.textures.borrow_mut()
+    /// Source files of every imported asset. Of the assets imported from the same file, the source
+    /// of the one imported the longest ago is returned.
+    pub fn sources(&self) -> BTreeMap<PathBuf, AssetSource> {
+        let mut sources = BTreeMap::<PathBuf, AssetSource>::new();
+
+        for source in self
+            .stores
+            .values()
+            .flat_map(|registered_store| registered_store.store.sources())
+        {
+            let is_older = match sources.get(&source.path) {
+                Some(oldest) => source.imported_at < oldest.imported_at,
+                None => true,
+            };
+
+            if is_older {
+                sources.insert(source.path.clone(), source);
+            }
+        }
+
+        sources
     }
 
-    pub fn materials(&self) -> Ref<'_, AssetStore<Material>> {
-        self.materials.borrow()
+    /// Takes the errors of assets read since the last call, and of manifests of registered types
+    /// that couldn't be read. Every error is returned once, as the action that failed and the
+    /// error.
+    pub fn take_errors(&self) -> Vec<(String, String)> {
+        self.stores
+            .values()
+            .flat_map(|registered_store| registered_store.store.take_errors())
+            .collect()
     }
 
-    pub fn materials_mut(&self) -> RefMut<'_, AssetStore<Material>> {
-        self.materials.borrow_mut()
+    fn store<T>(&self) -> &RwLock<AssetStore<T>>
+    where
+        T: AssetType,
+    {
+        self.stores
+            .get(&TypeId::of::<T>())
+            .expect(&format!("asset type: '{}' isn't registered", T::NAME))
+            .store
+            .as_any()
+            .downcast_ref::<RwLock<AssetStore<T>>>()
+            .unwrap()
     }
 }
@@ -0,0 +1,178 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::{self, Display},
+    mem,
+};
+
+use super::{asset_id::AssetId, AssetServer, AssetType};
+use crate::Id;
+
+/// Why [`AssetServer::remove`] refused to remove an asset.
+#[derive(Debug)]
+pub enum AssetInUse {
+    /// Ids of the assets depending on it.
+    ByAssets(Vec<Id>),
+    /// It is one of the roots, like a model placed in the scene.
+    ByRoots,
+}
+
+impl Display for AssetInUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetInUse::ByAssets(ids) => write!(f, "used by {} assets", ids.len()),
+            AssetInUse::ByRoots => f.write_str("used in the scene"),
+        }
+    }
+}
+
+impl AssetServer {
+    /// Every asset of every registered type together with the ids of its dependencies.
+    fn reference_graph(&self) -> BTreeMap<Id, Vec<Id>> {
+        self.stores
+            .values()
+            .flat_map(|registered_store| registered_store.store.dependencies())
+            .collect()
+    }
+
+    fn remove_by_id(&self, id: Id) {
+        for registered_store in self.stores.values() {
+            if registered_store.store.remove(id) {
+                self.removed_ids.lock().unwrap().push(id);
+                return;
+            }
+        }
+    }
+
+    /// Ids of the assets removed since the last call, see [`super::AssetRemoved`].
+    pub fn take_removed_ids(&self) -> Vec<Id> {
+        mem::take(&mut *self.removed_ids.lock().unwrap())
+    }
+
+    /// Ids of the assets that depend on the asset with `id`.
+    pub fn find_references(&self, id: Id) -> Vec<Id> {
+        referencing_ids(&self.reference_graph(), id)
+    }
+
+    /// Removes the asset unless it is referenced by another asset or one of `roots`, together with
+    /// the dependencies only it used. Returns the ids of every removed asset.
+    pub fn remove<T>(
+        &self,
+        asset_id: &AssetId<T>,
+        roots: &HashSet<Id>,
+    ) -> Result<Vec<Id>, AssetInUse>
+    where
+        T: AssetType,
+    {
+        let id = asset_id.id();
+
+        if roots.contains(&id) {
+            return Err(AssetInUse::ByRoots);
+        }
+
+        if !self.assets::<T>().contains(asset_id) {
+            return Ok(vec![]);
+        }
+
+        let graph = self.reference_graph();
+
+        let referencing_ids = referencing_ids(&graph, id);
+        if !referencing_ids.is_empty() {
+            return Err(AssetInUse::ByAssets(referencing_ids));
+        }
+
+        let dependencies_of = |id: Id| graph.get(&id).map_or(&[][..], Vec::as_slice);
+
+        let mut removed_ids = vec![id];
+        let mut candidate_ids = dependencies_of(id).to_vec();
+
+        // Removing a dependency can leave its own dependencies unreferenced
+        loop {
+            let referenced_ids = graph
+                .iter()
+                .filter(|(graph_id, _)| !removed_ids.contains(graph_id))
+                .flat_map(|(_, dependencies)| dependencies)
+                .collect::<HashSet<_>>();
+
+            let unreferenced_ids = candidate_ids
+                .iter()
+                .copied()
+                .filter(|candidate_id| {
+                    !removed_ids.contains(candidate_id)
+                        && !referenced_ids.contains(candidate_id)
+                        && !roots.contains(candidate_id)
+                })
+                .collect::<BTreeSet<_>>();
+
+            if unreferenced_ids.is_empty() {
+                break;
+            }
+
+            for unreferenced_id in unreferenced_ids {
+                removed_ids.push(unreferenced_id);
+                candidate_ids.extend_from_slice(dependencies_of(unreferenced_id));
+            }
+        }
+
+        for removed_id in &removed_ids {
+            self.remove_by_id(*removed_id);
+        }
+
+        Ok(removed_ids)
+    }
+
+    /// Name of the asset with `id`, whatever its type.
+    pub fn name(&self, id: Id) -> Option<String> {
+        self.stores
+            .values()
+            .find_map(|registered_store| registered_store.store.name(id))
+    }
+
+    /// Ids of every asset that can't be reached from `roots` through dependencies.
+    pub fn find_garbage(&self, roots: &HashSet<Id>) -> Vec<Id> {
+        let graph = self.reference_graph();
+
+        let mut reachable_ids = HashSet::new();
+        let mut pending_ids = roots.iter().copied().collect::<Vec<_>>();
+
+        while let Some(id) = pending_ids.pop() {
+            if !reachable_ids.insert(id) {
+                continue;
+            }
+
+            if let Some(dependencies) = graph.get(&id) {
+                pending_ids.extend_from_slice(dependencies);
+            }
+        }
+
+        graph
+            .into_iter()
+            .map(|(id, _)| id)
+            .filter(|id| !reachable_ids.contains(id))
+            .collect()
+    }
+
+    /// Removes the assets of `ids` that still can't be reached from `roots`, like the ones of
+    /// [`AssetServer::find_garbage`] after the user confirmed them. Returns the ids of the removed
+    /// assets.
+    pub fn collect_garbage(&self, roots: &HashSet<Id>, ids: &[Id]) -> Vec<Id> {
+        let removed_ids = self
+            .find_garbage(roots)
+            .into_iter()
+            .filter(|id| ids.contains(id))
+            .collect::<Vec<_>>();
+
+        for removed_id in &removed_ids {
+            self.remove_by_id(*removed_id);
+        }
+
+        removed_ids
+    }
+}
+
+fn referencing_ids(graph: &BTreeMap<Id, Vec<Id>>, id: Id) -> Vec<Id> {
+    graph
+        .iter()
+        .filter(|(_, dependencies)| dependencies.contains(&id))
+        .map(|(referencing_id, _)| *referencing_id)
+        .collect()
+}
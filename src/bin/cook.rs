@@ -0,0 +1,121 @@
+use space_game::{
+    asset_server::AssetServer,
+    cooking,
+    importing::{self, ImportSettings},
+    rendering::{
+        material::Material,
+        model::{Mesh, Model},
+        texture::Texture,
+    },
+};
+use std::{env, process, time::Instant};
+
+/// Imports source files and cooks them into a pack file, without a window or GPU so it can run in
+/// batch scripts: `cook [--assets <asset directory>] <pack path> <source paths>...`. glTF files
+/// and images are imported, the assets of the asset directory are cooked along with them. Games
+/// built with the `cooked` feature load the pack at [`cooking::pack::DEFAULT_PACK_PATH`] instead
+/// of the asset directory.
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+
+    let mut asset_server = AssetServer::default();
+
+    asset_server.register::<Model>();
+    asset_server.register::<Mesh>();
+    asset_server.register::<Texture>();
+    asset_server.register::<Material>();
+
+    if args.next_if_eq("--assets").is_some() {
+        let directory = args.next().expect("usage: --assets <asset directory>");
+
+        asset_server
+            .read_from_directory(&directory)
+            .expect(&format!("failed reading assets: '{}'", directory));
+    }
+
+    let Some(pack_path) = args.next() else {
+        panic!("usage: cook [--assets <asset directory>] <pack path> <source paths>...");
+    };
+
+    let mut failed = false;
+
+    for source_path in args {
+        let Some(settings) = ImportSettings::from_extension(&source_path) else {
+            println!("no importer for: '{}'", source_path);
+            failed = true;
+            continue;
+        };
+
+        let start = Instant::now();
+
+        match importing::import_blocking(&source_path, &settings, &asset_server) {
+            Ok(imported_ids) => println!(
+                "imported '{}': {} assets in {:.1?}",
+                source_path,
+                imported_ids.len(),
+                start.elapsed()
+            ),
+            Err(error) => {
+                println!("failed importing: '{}': {}", source_path, error);
+                failed = true;
+            }
+        }
+    }
+
+    let start = Instant::now();
+    let (cooked_assets, pack_size) = cooking::cook(&asset_server, &pack_path)
+        .expect(&format!("failed writing pack: '{}'", pack_path));
+
+    for failed_asset in &cooked_assets.failed {
+        println!(
+            "failed cooking {}: '{}' ({}): {}",
+            failed_asset.kind,
+            failed_asset.id,
+            failed_asset.name.as_deref().unwrap_or(""),
+            failed_asset.error
+        );
+        failed = true;
+    }
+
+    println!();
+    println!(
+        "{:<10} {:<22} {:<32} {:>12} {:>12}",
+        "kind", "id", "name", "size", "time"
+    );
+
+    for cooked_asset in &cooked_assets.cooked {
+        println!(
+            "{:<10} {:<22} {:<32} {:>12} {:>12.1?}",
+            cooked_asset.kind,
+            // Display of ids ignores the width
+            cooked_asset.id.to_string(),
+            cooked_asset.name.as_deref().unwrap_or(""),
+            format_size(cooked_asset.size),
+            cooked_asset.duration
+        );
+    }
+
+    println!();
+    println!(
+        "cooked {} assets into '{}': {} in {:.1?}",
+        cooked_assets.cooked.len(),
+        pack_path,
+        format_size(pack_size),
+        start.elapsed()
+    );
+
+    // So scripts notice sources and assets that were left out
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
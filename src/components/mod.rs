@@ -1,4 +1,46 @@
+use crate::scene::scene_object::SceneObject;
+
+use self::{camera::CameraComponent, light::LightComponent, model::ModelComponent};
+
 pub mod camera;
 pub mod light;
 pub mod model;
 pub mod transform;
+
+/// Optional part of a scene object, lets [`Commands`](crate::app::commands::Commands) add and
+/// remove components without knowing which field they are stored in.
+pub trait Component: Send + 'static {
+    fn insert(self, scene_object: &mut SceneObject);
+
+    fn remove(scene_object: &mut SceneObject);
+}
+
+impl Component for ModelComponent {
+    fn insert(self, scene_object: &mut SceneObject) {
+        scene_object.model_component = Some(self);
+    }
+
+    fn remove(scene_object: &mut SceneObject) {
+        scene_object.model_component = None;
+    }
+}
+
+impl Component for LightComponent {
+    fn insert(self, scene_object: &mut SceneObject) {
+        scene_object.light_component = Some(self);
+    }
+
+    fn remove(scene_object: &mut SceneObject) {
+        scene_object.light_component = None;
+    }
+}
+
+impl Component for CameraComponent {
+    fn insert(self, scene_object: &mut SceneObject) {
+        scene_object.camera_component = Some(self);
+    }
+
+    fn remove(scene_object: &mut SceneObject) {
+        scene_object.camera_component = None;
+    }
+}
@@ -31,11 +31,11 @@ pub fn draw(
                     "empty",
                 );
 
-                for model in asset_server.models().iter() {
+                for (model_id, metadata) in asset_server.assets::<Model>().iter_metadata() {
                     ui.selectable_value(
                         &mut model_component.model_id,
-                        model.id(),
-                        model.metadata.name.as_ref().unwrap(),
+                        model_id,
+                        metadata.name.as_ref().unwrap(),
                     );
                 }
             });
@@ -0,0 +1,262 @@
+use glam::Vec4;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    asset_server::{Asset, AssetServer, AssetType},
+    rendering::{
+        material::Material,
+        model::{Mesh, Model},
+        texture::Texture,
+    },
+    Id,
+};
+
+use self::pack::PackWriter;
+
+pub mod pack;
+
+/// Asset written to a pack, for reporting what the pack is made of.
+pub struct CookedAsset {
+    pub kind: &'static str,
+    pub id: Id,
+    pub name: Option<String>,
+    /// Bytes the asset takes up in the pack.
+    pub size: usize,
+    pub duration: Duration,
+}
+
+/// Asset left out of a pack because it couldn't be cooked.
+pub struct FailedAsset {
+    pub kind: &'static str,
+    pub id: Id,
+    pub name: Option<String>,
+    pub error: CookError,
+}
+
+#[derive(Debug)]
+pub enum CookError {
+    /// The importers don't produce textures of the format, so there is no cooking for it.
+    UnsupportedTextureFormat(wgpu::TextureFormat),
+    /// The texture has a different number of bytes than its size and format call for.
+    TextureSizeMismatch,
+}
+
+impl Display for CookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CookError::UnsupportedTextureFormat(format) => {
+                write!(f, "unsupported texture format: {:?}", format)
+            }
+            CookError::TextureSizeMismatch => {
+                f.write_str("texture data doesn't match its size and format")
+            }
+        }
+    }
+}
+
+impl Error for CookError {}
+
+/// Texture in the format it's uploaded in, with its full mip chain.
+pub struct CookedTexture {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    /// The largest level first, down to 1x1.
+    pub mips: Vec<Vec<u8>>,
+}
+
+/// Assets written to a pack and the ones left out of it.
+#[derive(Default)]
+pub struct CookedAssets {
+    pub cooked: Vec<CookedAsset>,
+    pub failed: Vec<FailedAsset>,
+}
+
+/// Builds the GPU ready data of every asset of the server and writes it to a pack file. Assets
+/// that can't be cooked are left out. Returns the assets and the size of the pack.
+pub fn cook<P>(asset_server: &AssetServer, path: &P) -> io::Result<(CookedAssets, usize)>
+where
+    P: AsRef<Path>,
+{
+    let mut pack_writer = PackWriter::default();
+    let mut cooked_assets = CookedAssets::default();
+
+    cook_assets::<Mesh, _>(asset_server, "mesh", &mut cooked_assets, |mesh| {
+        Ok(pack_writer.add_mesh(mesh.id(), &mesh.vertices(), &mesh.indices))
+    });
+    cook_assets::<Texture, _>(asset_server, "texture", &mut cooked_assets, |texture| {
+        Ok(pack_writer.add_texture(texture.id(), &cook_texture(texture)?))
+    });
+    cook_assets::<Material, _>(asset_server, "material", &mut cooked_assets, |material| {
+        Ok(pack_writer.add_material(material.id(), material.asset.clone()))
+    });
+    cook_assets::<Model, _>(asset_server, "model", &mut cooked_assets, |model| {
+        Ok(pack_writer.add_model(model.id(), model.asset.clone()))
+    });
+
+    let pack_size = pack_writer.write(path)?;
+
+    Ok((cooked_assets, pack_size))
+}
+
+fn cook_assets<T, F>(
+    asset_server: &AssetServer,
+    kind: &'static str,
+    cooked_assets: &mut CookedAssets,
+    mut cook_asset: F,
+) where
+    T: AssetType,
+    F: FnMut(&Asset<T>) -> Result<usize, CookError>,
+{
+    for asset in asset_server.assets::<T>().iter() {
+        let start = Instant::now();
+
+        match cook_asset(asset) {
+            Ok(size) => cooked_assets.cooked.push(CookedAsset {
+                kind,
+                id: asset.id().id(),
+                name: asset.metadata.name.clone(),
+                size,
+                duration: start.elapsed(),
+            }),
+            Err(error) => cooked_assets.failed.push(FailedAsset {
+                kind,
+                id: asset.id().id(),
+                name: asset.metadata.name.clone(),
+                error,
+            }),
+        }
+    }
+}
+
+/// Expands the texture to four channels, which is what the renderer samples, and builds its mip
+/// chain with a box filter.
+pub fn cook_texture(texture: &Texture) -> Result<CookedTexture, CookError> {
+    let (channel_type, channel_count) = texel_layout(texture.format)?;
+    let texel_size = channel_type.size() * channel_count;
+
+    if texture.bytes.len() as u64
+        != texture.width as u64 * texture.height as u64 * texel_size as u64
+    {
+        return Err(CookError::TextureSizeMismatch);
+    }
+
+    let mut texels = texture
+        .bytes
+        .chunks_exact(texel_size)
+        .map(|texel| {
+            let channel = |i: usize| {
+                channel_type.decode(&texel[i * channel_type.size()..(i + 1) * channel_type.size()])
+            };
+
+            // Single and dual channel images are luminance and luminance with alpha
+            match channel_count {
+                1 => Vec4::new(channel(0), channel(0), channel(0), 1.0),
+                2 => Vec4::new(channel(0), channel(0), channel(0), channel(1)),
+                _ => Vec4::new(channel(0), channel(1), channel(2), channel(3)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let (mut width, mut height) = (texture.width, texture.height);
+    let mut mips = vec![channel_type.encode(&texels)];
+
+    while width > 1 || height > 1 {
+        (texels, width, height) = downsample(&texels, width, height);
+        mips.push(channel_type.encode(&texels));
+    }
+
+    Ok(CookedTexture {
+        width: texture.width,
+        height: texture.height,
+        format: channel_type.rgba_format(),
+        mips,
+    })
+}
+
+/// Averages every 2x2 block into one texel, the last row or column of odd sizes is dropped.
+fn downsample(texels: &[Vec4], width: u32, height: u32) -> (Vec<Vec4>, u32, u32) {
+    let (mip_width, mip_height) = ((width / 2).max(1), (height / 2).max(1));
+    let texel = |x: u32, y: u32| texels[(y.min(height - 1) * width + x.min(width - 1)) as usize];
+
+    let mip_texels = (0..mip_height)
+        .flat_map(|y| (0..mip_width).map(move |x| (x * 2, y * 2)))
+        .map(|(x, y)| (texel(x, y) + texel(x + 1, y) + texel(x, y + 1) + texel(x + 1, y + 1)) / 4.0)
+        .collect();
+
+    (mip_texels, mip_width, mip_height)
+}
+
+#[derive(Clone, Copy)]
+enum ChannelType {
+    Unorm8,
+    Unorm16,
+    Float32,
+}
+
+impl ChannelType {
+    fn size(self) -> usize {
+        match self {
+            ChannelType::Unorm8 => 1,
+            ChannelType::Unorm16 => 2,
+            ChannelType::Float32 => 4,
+        }
+    }
+
+    fn rgba_format(self) -> wgpu::TextureFormat {
+        match self {
+            ChannelType::Unorm8 => wgpu::TextureFormat::Rgba8Unorm,
+            ChannelType::Unorm16 => wgpu::TextureFormat::Rgba16Unorm,
+            ChannelType::Float32 => wgpu::TextureFormat::Rgba32Float,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            ChannelType::Unorm8 => bytes[0] as f32 / u8::MAX as f32,
+            ChannelType::Unorm16 => {
+                u16::from_le_bytes(bytes.try_into().unwrap()) as f32 / u16::MAX as f32
+            }
+            ChannelType::Float32 => f32::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+
+    /// Encodes the texels as RGBA.
+    fn encode(self, texels: &[Vec4]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(texels.len() * 4 * self.size());
+
+        for channel in texels.iter().flat_map(|texel| texel.to_array()) {
+            match self {
+                ChannelType::Unorm8 => {
+                    bytes.push((channel.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8)
+                }
+                ChannelType::Unorm16 => bytes.extend_from_slice(
+                    &((channel.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16).to_le_bytes(),
+                ),
+                ChannelType::Float32 => bytes.extend_from_slice(&channel.to_le_bytes()),
+            }
+        }
+
+        bytes
+    }
+}
+
+/// Channel type and count of the formats the importers produce.
+fn texel_layout(format: wgpu::TextureFormat) -> Result<(ChannelType, usize), CookError> {
+    match format {
+        wgpu::TextureFormat::R8Unorm => Ok((ChannelType::Unorm8, 1)),
+        wgpu::TextureFormat::Rg8Unorm => Ok((ChannelType::Unorm8, 2)),
+        wgpu::TextureFormat::Rgba8Unorm => Ok((ChannelType::Unorm8, 4)),
+        wgpu::TextureFormat::R16Unorm => Ok((ChannelType::Unorm16, 1)),
+        wgpu::TextureFormat::Rg16Unorm => Ok((ChannelType::Unorm16, 2)),
+        wgpu::TextureFormat::Rgba16Unorm => Ok((ChannelType::Unorm16, 4)),
+        wgpu::TextureFormat::Rgba32Float => Ok((ChannelType::Float32, 4)),
+        _ => Err(CookError::UnsupportedTextureFormat(format)),
+    }
+}
@@ -0,0 +1,305 @@
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::{fs, fs::File, io, mem, path::Path};
+
+use crate::{
+    asset_server::{asset_id::AssetId, AssetMetadata, AssetServer},
+    file_format::FileFormatError,
+    rendering::{
+        material::Material,
+        model::{Mesh, Model, Vertex},
+        texture::Texture,
+    },
+};
+
+use super::CookedTexture;
+
+pub const PACK_MAGIC: [u8; 4] = *b"SGPK";
+
+/// Builds with the `cooked` feature load their assets from this pack instead of the asset
+/// directory.
+pub const DEFAULT_PACK_PATH: &'static str = "./assets.pack";
+
+/// Blobs are read in place from the mapped file, so packs of an older version are cooked again
+/// instead of migrated.
+pub const PACK_VERSION: u32 = 1;
+
+/// Magic number, version, offset and length of the index, padded to the blob alignment.
+const HEADER_SIZE: usize = 32;
+
+/// Enough for any vertex, index or texel type, so blobs can be cast in place.
+const BLOB_ALIGNMENT: usize = 16;
+
+/// Location of a blob in the pack file.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub len: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackedMesh {
+    pub id: AssetId<Mesh>,
+    /// Interleaved [`Vertex`] data.
+    pub vertices: ByteRange,
+    /// `u32` indices.
+    pub indices: ByteRange,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackedTexture {
+    pub id: AssetId<Texture>,
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    /// Every mip level, the largest first.
+    pub mips: Vec<ByteRange>,
+}
+
+impl PackedTexture {
+    /// Size of the mip level, halved with every level down to a single texel.
+    pub fn mip_size(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+}
+
+/// Describes the content of a pack. Materials and models are small and stored in the index
+/// itself.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PackIndex {
+    pub meshes: Vec<PackedMesh>,
+    pub textures: Vec<PackedTexture>,
+    pub materials: Vec<(AssetId<Material>, Material)>,
+    pub models: Vec<(AssetId<Model>, Model)>,
+}
+
+impl PackIndex {
+    fn blobs(&self) -> impl Iterator<Item = ByteRange> + '_ {
+        self.meshes
+            .iter()
+            .flat_map(|mesh| [mesh.vertices, mesh.indices])
+            .chain(
+                self.textures
+                    .iter()
+                    .flat_map(|texture| texture.mips.iter().copied()),
+            )
+    }
+}
+
+/// Collects the blobs and index of a pack before it's written.
+#[derive(Default)]
+pub struct PackWriter {
+    blobs: Vec<u8>,
+    index: PackIndex,
+}
+
+impl PackWriter {
+    fn add_blob(&mut self, bytes: &[u8]) -> ByteRange {
+        let aligned_len = self.blobs.len().next_multiple_of(BLOB_ALIGNMENT);
+        self.blobs.resize(aligned_len, 0);
+
+        let offset = (HEADER_SIZE + self.blobs.len()) as u64;
+        self.blobs.extend_from_slice(bytes);
+
+        ByteRange {
+            offset,
+            len: bytes.len() as u64,
+        }
+    }
+
+    /// Returns the size of the added data.
+    pub fn add_mesh(&mut self, id: AssetId<Mesh>, vertices: &[Vertex], indices: &[u32]) -> usize {
+        let vertices = self.add_blob(bytemuck::cast_slice(vertices));
+        let indices = self.add_blob(bytemuck::cast_slice(indices));
+
+        self.index.meshes.push(PackedMesh {
+            id,
+            vertices,
+            indices,
+        });
+
+        (vertices.len + indices.len) as usize
+    }
+
+    /// Returns the size of the added data.
+    pub fn add_texture(&mut self, id: AssetId<Texture>, texture: &CookedTexture) -> usize {
+        let mips = texture
+            .mips
+            .iter()
+            .map(|mip| self.add_blob(mip))
+            .collect::<Vec<_>>();
+        let size = mips.iter().map(|mip| mip.len as usize).sum();
+
+        self.index.textures.push(PackedTexture {
+            id,
+            width: texture.width,
+            height: texture.height,
+            format: texture.format,
+            mips,
+        });
+
+        size
+    }
+
+    /// Returns the size of the material in the index.
+    pub fn add_material(&mut self, id: AssetId<Material>, material: Material) -> usize {
+        let size = bincode::serialized_size(&material).unwrap() as usize;
+        self.index.materials.push((id, material));
+
+        size
+    }
+
+    /// Returns the size of the model in the index.
+    pub fn add_model(&mut self, id: AssetId<Model>, model: Model) -> usize {
+        let size = bincode::serialized_size(&model).unwrap() as usize;
+        self.index.models.push((id, model));
+
+        size
+    }
+
+    /// Returns the size of the written file.
+    pub fn write<P>(&self, path: &P) -> io::Result<usize>
+    where
+        P: AsRef<Path>,
+    {
+        let index = bincode::serialize(&self.index).unwrap();
+        let index_offset = HEADER_SIZE + self.blobs.len();
+
+        let mut bytes = Vec::with_capacity(index_offset + index.len());
+        bytes.extend_from_slice(&PACK_MAGIC);
+        bytes.extend_from_slice(&PACK_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(index_offset as u64).to_le_bytes());
+        bytes.extend_from_slice(&(index.len() as u64).to_le_bytes());
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend_from_slice(&self.blobs);
+        bytes.extend_from_slice(&index);
+
+        fs::write(path, &bytes)?;
+
+        Ok(bytes.len())
+    }
+}
+
+/// Pack file mapped into memory. Only the index is deserialized, vertex, index and texel data is
+/// borrowed from the mapping and can be uploaded to the GPU as is.
+pub struct AssetPack {
+    mmap: Mmap,
+    index: PackIndex,
+}
+
+impl AssetPack {
+    pub fn open<P>(path: &P) -> Result<Self, FileFormatError>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(path)?;
+        // Packs are only written by the cooker, not while the game is running
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        if mmap.len() < HEADER_SIZE {
+            return Err(FileFormatError::Corrupt(String::from("truncated header")));
+        }
+
+        if mmap[0..4] != PACK_MAGIC {
+            return Err(FileFormatError::UnknownFormat);
+        }
+
+        let version = u32::from_le_bytes(mmap[4..8].try_into().unwrap());
+
+        if version > PACK_VERSION {
+            return Err(FileFormatError::UnsupportedVersion {
+                version,
+                supported_version: PACK_VERSION,
+            });
+        } else if version < PACK_VERSION {
+            return Err(FileFormatError::Corrupt(format!(
+                "pack version {} has to be cooked again",
+                version
+            )));
+        }
+
+        let index_offset = u64::from_le_bytes(mmap[8..16].try_into().unwrap()) as usize;
+        let index_len = u64::from_le_bytes(mmap[16..24].try_into().unwrap()) as usize;
+
+        let index_bytes = index_offset
+            .checked_add(index_len)
+            .and_then(|index_end| mmap.get(index_offset..index_end))
+            .ok_or_else(|| FileFormatError::Corrupt(String::from("index out of bounds")))?;
+
+        let index = bincode::deserialize::<PackIndex>(index_bytes)
+            .map_err(|error| FileFormatError::Corrupt(error.to_string()))?;
+
+        let is_blob_valid = |blob: ByteRange| {
+            blob.offset as usize % BLOB_ALIGNMENT == 0
+                && blob
+                    .offset
+                    .checked_add(blob.len)
+                    .is_some_and(|blob_end| blob_end as usize <= index_offset)
+        };
+        let are_meshes_valid = index.meshes.iter().all(|mesh| {
+            mesh.vertices.len as usize % mem::size_of::<Vertex>() == 0
+                && mesh.indices.len as usize % mem::size_of::<u32>() == 0
+        });
+        // The renderer uploads the mips as they are, so they have to match the texture
+        let are_textures_valid = index.textures.iter().all(|texture| {
+            let mip_count = 32 - texture.width.max(texture.height).leading_zeros() as usize;
+
+            matches!(
+                texture.format,
+                wgpu::TextureFormat::Rgba8Unorm
+                    | wgpu::TextureFormat::Rgba16Unorm
+                    | wgpu::TextureFormat::Rgba32Float
+            ) && texture.width > 0
+                && texture.height > 0
+                && texture.mips.len() == mip_count
+                && texture.mips.iter().enumerate().all(|(level, mip)| {
+                    let (width, height) = texture.mip_size(level);
+                    let texel_size = texture.format.block_size(None).unwrap() as u64;
+
+                    mip.len == texel_size * width as u64 * height as u64
+                })
+        });
+
+        if !index.blobs().all(is_blob_valid) || !are_meshes_valid || !are_textures_valid {
+            return Err(FileFormatError::Corrupt(String::from("invalid blob")));
+        }
+
+        Ok(Self { mmap, index })
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    fn blob(&self, blob: ByteRange) -> &[u8] {
+        &self.mmap[blob.offset as usize..(blob.offset + blob.len) as usize]
+    }
+
+    pub fn vertices(&self, mesh: &PackedMesh) -> &[Vertex] {
+        bytemuck::cast_slice(self.blob(mesh.vertices))
+    }
+
+    pub fn indices(&self, mesh: &PackedMesh) -> &[u32] {
+        bytemuck::cast_slice(self.blob(mesh.indices))
+    }
+
+    pub fn mip(&self, texture: &PackedTexture, level: usize) -> &[u8] {
+        self.blob(texture.mips[level])
+    }
+
+    /// Adds the materials and models of the pack to the asset server with the ids they were
+    /// cooked with. Meshes and textures stay in the pack, the renderer uploads them from it.
+    pub fn add_to(&self, asset_server: &AssetServer) {
+        let mut materials = asset_server.assets_mut::<Material>();
+
+        for (material_id, material) in &self.index.materials {
+            materials.add_or_replace(*material_id, material.clone(), AssetMetadata::default());
+        }
+
+        let mut models = asset_server.assets_mut::<Model>();
+
+        for (model_id, model) in &self.index.models {
+            models.add_or_replace(*model_id, model.clone(), AssetMetadata::default());
+        }
+    }
+}
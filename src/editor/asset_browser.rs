@@ -1,59 +1,263 @@
 use egui::*;
 use native_dialog::FileDialog;
 
-use crate::app::{Res, ResMut};
-use crate::asset_server::{self, AssetServer};
-use crate::editor::Editor;
-use crate::importing;
+use std::collections::BTreeMap;
 
-pub fn update(editor: Res<Editor>, context: Res<egui::Context>, asset_server: ResMut<AssetServer>) {
+use crate::app::{Local, Res, ResMut};
+use crate::asset_server::asset_id::AssetId;
+use crate::asset_server::jobs::{AssetJobs, JobStatus};
+use crate::asset_server::{self, AssetMetadata, AssetServer};
+use crate::error::ErrorLog;
+use crate::importing::{self, ImportSettings};
+use crate::rendering::model::Model;
+use crate::scene::Scene;
+use crate::Id;
+
+#[derive(Default, PartialEq, Clone, Copy)]
+pub enum Grouping {
+    #[default]
+    None,
+    Source,
+    Tag,
+}
+
+#[derive(Default)]
+pub struct AssetFilter {
+    /// Matched against the names and tags of the assets, ignoring case.
+    pub search: String,
+    pub grouping: Grouping,
+}
+
+impl AssetFilter {
+    fn matches(&self, metadata: &AssetMetadata) -> bool {
+        let search = self.search.to_lowercase();
+
+        search.is_empty()
+            || metadata
+                .name
+                .iter()
+                .chain(&metadata.tags)
+                .any(|text| text.to_lowercase().contains(&search))
+    }
+
+    /// Names of the groups the asset is shown in, an asset is shown under each of its tags.
+    fn groups(&self, metadata: &AssetMetadata) -> Vec<String> {
+        match self.grouping {
+            Grouping::None => vec![String::new()],
+            Grouping::Source => match &metadata.source {
+                Some(source) => vec![source.path.display().to_string()],
+                None => vec![String::from("no source")],
+            },
+            Grouping::Tag if metadata.tags.is_empty() => vec![String::from("untagged")],
+            Grouping::Tag => metadata.tags.iter().cloned().collect(),
+        }
+    }
+}
+
+pub fn update(
+    context: Res<egui::Context>,
+    asset_server: Res<AssetServer>,
+    scene: Res<Scene>,
+    asset_jobs: ResMut<AssetJobs>,
+    error_log: ResMut<ErrorLog>,
+    filter: Local<AssetFilter>,
+    garbage_ids: Local<Option<Vec<Id>>>,
+) {
     let context = context.get();
-    let editor = editor.get();
-    let mut asset_server = asset_server.get_mut();
+    let asset_server = asset_server.get();
+    let scene = scene.get();
+    let mut asset_jobs = asset_jobs.get_mut();
+    let mut error_log = error_log.get_mut();
+    let mut filter = filter.get_mut();
+    let mut garbage_ids = garbage_ids.get_mut();
 
     Window::new("Asset browser")
         .min_width(512.0)
         .show(&context, |ui| {
             let column_count = 5;
 
-            if ui.button("add model").clicked() || editor.file_browser_open {
+            if ui.button("add model").clicked() {
                 let path = FileDialog::new()
                     .add_filter("GLTF Model", &["gltf"])
-                    .show_open_single_file()
-                    .unwrap();
+                    .show_open_single_file();
+
+                match path {
+                    Ok(Some(path)) => {
+                        println!("loading model from path: {:?}", &path);
 
-                if let Some(path) = path {
-                    println!("loading model from path: {:?}", &path);
-                    importing::gltf::load(&path, &mut asset_server);
+                        let settings = ImportSettings::Gltf(Default::default());
+                        importing::import(&path, &settings, &mut asset_jobs);
+                    }
+                    Ok(None) => {}
+                    Err(error) => error_log.report("failed opening file dialog", error),
                 }
             };
 
             if ui.button("save assets").clicked() {
-                asset_server.write_to_file(&asset_server::DEFAULT_PATH);
+                if let Err(error) =
+                    asset_server.write_to_directory(&asset_server::DEFAULT_DIRECTORY)
+                {
+                    error_log.report("failed saving assets", error);
+                }
+            }
+
+            if ui.button("remove unused assets").clicked() {
+                *garbage_ids = Some(asset_server.find_garbage(&scene.asset_references()));
+            }
+
+            if !asset_jobs.is_empty() {
+                ui.separator();
+
+                let mut dismissed_job_id = None;
+
+                for (job_id, job) in asset_jobs.iter() {
+                    ui.horizontal(|ui| {
+                        ui.label(&job.name);
+
+                        match &job.status {
+                            JobStatus::Running if job.is_cancelled() => {
+                                ui.label("cancelling");
+                            }
+                            JobStatus::Running => {
+                                ui.add(
+                                    ProgressBar::new(job.progress())
+                                        .desired_width(200.0)
+                                        .show_percentage(),
+                                );
+
+                                if ui.button("cancel").clicked() {
+                                    asset_jobs.cancel(job_id);
+                                }
+                            }
+                            JobStatus::Failed(error) => {
+                                ui.colored_label(Color32::RED, error);
+
+                                if ui.button("dismiss").clicked() {
+                                    dismissed_job_id = Some(job_id);
+                                }
+                            }
+                        }
+                    });
+                }
+
+                if let Some(dismissed_job_id) = dismissed_job_id {
+                    asset_jobs.dismiss(dismissed_job_id);
+                }
             }
 
             ui.separator();
 
+            ui.horizontal(|ui| {
+                ui.label("search");
+                ui.text_edit_singleline(&mut filter.search);
+
+                ui.label("group by");
+                ui.radio_value(&mut filter.grouping, Grouping::None, "none");
+                ui.radio_value(&mut filter.grouping, Grouping::Source, "source");
+                ui.radio_value(&mut filter.grouping, Grouping::Tag, "tag");
+            });
+
             ScrollArea::vertical().show(ui, |scroll_area| {
-                let mut models = asset_server.models_mut();
-                
-                scroll_area.columns(column_count, |columns| {
-                    for mut i in 0..models.len() as isize {
-                        let wrapped_index = i as usize % column_count;
+                let models = asset_server.assets::<Model>();
+                let mut removed_model_id = None;
 
-                        columns[wrapped_index].group(|ui| {
-                            if let Some(mesh) = models.get_at_index(i as usize) {
-                                ui.label(mesh.metadata.name.as_ref().unwrap());
+                let mut groups = BTreeMap::<String, Vec<(AssetId<Model>, &AssetMetadata)>>::new();
 
-                                if ui.button("delete").clicked() {
-                                    models.remove_at_index(i as usize);
+                for (model_id, metadata) in models.iter_metadata() {
+                    if filter.matches(metadata) {
+                        for group in filter.groups(metadata) {
+                            groups.entry(group).or_default().push((model_id, metadata));
+                        }
+                    }
+                }
 
-                                    i -= 1;
-                                }
+                for (group, group_models) in &groups {
+                    let mut show_models = |ui: &mut Ui| {
+                        ui.columns(column_count, |columns| {
+                            for (i, (model_id, metadata)) in group_models.iter().enumerate() {
+                                let wrapped_index = i % column_count;
+
+                                columns[wrapped_index].group(|ui| {
+                                    ui.label(metadata.name.as_ref().unwrap());
+
+                                    if !metadata.tags.is_empty() {
+                                        let tags = metadata
+                                            .tags
+                                            .iter()
+                                            .cloned()
+                                            .collect::<Vec<_>>()
+                                            .join(", ");
+                                        ui.small(tags);
+                                    }
+
+                                    if ui.button("delete").clicked() {
+                                        removed_model_id = Some(*model_id);
+                                    }
+                                });
                             }
                         });
+                    };
+
+                    if filter.grouping == Grouping::None {
+                        show_models(scroll_area);
+                    } else {
+                        CollapsingHeader::new(format!("{} ({})", group, group_models.len()))
+                            .default_open(true)
+                            .show(scroll_area, show_models);
+                    }
+                }
+
+                drop(groups);
+                drop(models);
+
+                if let Some(removed_model_id) = removed_model_id {
+                    if let Err(error) =
+                        asset_server.remove(&removed_model_id, &scene.asset_references())
+                    {
+                        error_log.report(
+                            &format!("failed removing model: '{}'", removed_model_id),
+                            error,
+                        );
                     }
-                })
+                }
+            });
+        });
+
+    let mut is_confirmation_closed = false;
+
+    if let Some(ids) = garbage_ids.as_ref() {
+        Window::new("Remove unused assets").show(&context, |ui| {
+            if ids.is_empty() {
+                ui.label("there are no unused assets");
+            } else {
+                ui.label(format!("{} assets aren't used by the scene:", ids.len()));
+
+                ScrollArea::vertical().max_height(256.0).show(ui, |ui| {
+                    for id in ids {
+                        ui.label(asset_server.name(*id).unwrap_or_else(|| id.to_string()));
+                    }
+                });
+            }
+
+            ui.horizontal(|ui| {
+                if !ids.is_empty() && ui.button("remove").clicked() {
+                    let removed_ids = asset_server.collect_garbage(&scene.asset_references(), ids);
+                    error_log.report(
+                        "removed unused assets",
+                        format!("{} of {}", removed_ids.len(), ids.len()),
+                    );
+
+                    is_confirmation_closed = true;
+                }
+
+                if ui.button("cancel").clicked() {
+                    is_confirmation_closed = true;
+                }
             });
         });
+    }
+
+    if is_confirmation_closed {
+        *garbage_ids = None;
+    }
 }
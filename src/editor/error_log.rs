@@ -0,0 +1,36 @@
+use egui::Window;
+
+use crate::app::{Res, ResMut};
+use crate::error::ErrorLog;
+
+/// Lists the errors in the error log, only shown while there are any.
+pub fn update(context: Res<egui::Context>, error_log: ResMut<ErrorLog>) {
+    let context = context.get();
+    let mut error_log = error_log.get_mut();
+
+    if error_log.is_empty() {
+        return;
+    }
+
+    Window::new("Errors").min_width(512.0).show(&context, |ui| {
+        let mut dismissed_index = None;
+
+        for (index, error) in error_log.errors().iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("dismiss").clicked() {
+                    dismissed_index = Some(index);
+                }
+
+                ui.label(error);
+            });
+        }
+
+        if let Some(dismissed_index) = dismissed_index {
+            error_log.dismiss(dismissed_index);
+        }
+
+        if ui.button("dismiss all").clicked() {
+            error_log.clear();
+        }
+    });
+}
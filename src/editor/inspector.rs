@@ -6,8 +6,8 @@ use crate::components::camera::CameraComponent;
 use crate::components::light::LightComponent;
 use crate::components::model::ModelComponent;
 use crate::editor::Editor;
-use crate::scene::SceneObjectId;
-use crate::{components, Scene};
+use crate::components;
+use crate::scene::{Scene, SceneObjectId};
 
 pub fn update(
     context: Res<egui::Context>,
@@ -27,7 +27,9 @@ pub fn update(
                 return;
             }
 
-            let sobj = scene.get_mut(editor.selected_scene_object_id).unwrap();
+            let Some(sobj) = scene.get_mut(editor.selected_scene_object_id) else {
+                return;
+            };
 
             ui.columns(2, |columns| {
                 columns[0].heading("Name");
@@ -60,7 +62,9 @@ pub fn update(
 }
 
 fn ui_tree_context_menu(ui: &mut Ui, editor: &Editor, scene: &mut Scene) {
-    let scene_object = scene.get_mut(editor.selected_scene_object_id).unwrap();
+    let Some(scene_object) = scene.get_mut(editor.selected_scene_object_id) else {
+        return;
+    };
 
     if ui.button("add model").clicked() {
         scene_object.model_component = Some(ModelComponent::default());
@@ -2,27 +2,78 @@ use egui::{CentralPanel, Frame};
 use egui_dock::{DockArea, Style, Tree};
 
 use crate::{
-    app::{Res, ResMut},
+    app::{App, Plugin, Res, ResMut, Stage, SystemLabel},
     asset_server::AssetServer,
-    scene::SceneObjectId,
-    Scene,
+    error::ErrorLog,
+    scene::{Scene, SceneObjectId},
+    ui,
 };
 
 pub mod asset_browser;
 pub mod scene_hierarchy;
 pub mod inspector;
 pub mod debugger;
+pub mod error_log;
+
+/// Label shared by the editor windows, all of which may edit the scene.
+pub const LABEL: SystemLabel = "editor";
+
+/// Editor windows. Requires the ui plugin.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_resource(Editor::new());
+        app.init_resource::<ErrorLog>();
+
+        // DOCKING EDITOR
+        // let tree = {
+        //     let mut tree = Tree::new(vec!["hierarchy".to_string()]);
+        //     let [hierarchy, inspector] =
+        //         tree.split_right(NodeIndex::root(), 0.8, vec!["inspector".to_string()]);
+
+        //     let [hierarchy, file_browser] = tree.split_below(
+        //         hierarchy,
+        //         0.6,
+        //         vec!["file browser".to_string(), "profiler".to_string()],
+        //     );
+
+        //     let [hierarchy, scene] = tree.split_right(hierarchy, 0.25, vec!["scene".to_string()]);
+        //     tree.split_right(scene, 0.5, vec!["game".to_string()]);
+        //     tree
+        // };
+        // app.add_resource::<Tree<String>>(tree);
+        // app.add_system(Stage::Update, _update).label(LABEL).after(ui::LABEL);
+        //
+
+        app.add_system(Stage::Update, scene_hierarchy::update)
+            .label(LABEL)
+            .after(ui::LABEL);
+        app.add_system(Stage::Update, inspector::update)
+            .label(LABEL)
+            .after(ui::LABEL);
+        // Opens file dialogs
+        app.add_system(Stage::Update, asset_browser::update)
+            .label(LABEL)
+            .after(ui::LABEL)
+            .on_main_thread();
+        app.add_system(Stage::Update, debugger::update)
+            .label(LABEL)
+            .after(ui::LABEL);
+        app.add_system(Stage::Update, error_log::update)
+            .label(LABEL)
+            .after(ui::LABEL);
+    }
+}
 
 pub struct Editor {
     selected_scene_object_id: SceneObjectId,
-    file_browser_open: bool,
 }
 
 impl Editor {
     pub fn new() -> Self {
         Self {
             selected_scene_object_id: SceneObjectId::EMPTY,
-            file_browser_open: false,
         }
     }
 }
@@ -32,8 +83,12 @@ pub fn _update(
     editor: ResMut<Editor>,
     scene: ResMut<Scene>,
     asset_server: ResMut<AssetServer>,
-    tree: ResMut<Tree<String>>,
+    tree: Option<ResMut<Tree<String>>>,
 ) {
+    let Some(tree) = tree else {
+        return;
+    };
+
     let context = context.get();
 
     CentralPanel::default()
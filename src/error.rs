@@ -0,0 +1,112 @@
+use std::{
+    error,
+    fmt::{self, Display},
+    io,
+};
+
+use crate::{file_format::FileFormatError, importing::ImportError, scene::SceneObjectId, Id};
+
+/// Error of the fallible asset server and scene operations, wraps the errors of the individual
+/// modules.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    FileFormat(FileFormatError),
+    Import(ImportError),
+    AssetNotFound(Id),
+    /// The file of the asset couldn't be read, the reason is reported when it's first read.
+    AssetUnreadable(Id),
+    /// The manifest entry of the asset type couldn't be read, the assets added to its store since
+    /// weren't saved so the entry isn't overwritten.
+    AssetsNotSaved(&'static str),
+    SceneObjectNotFound(SceneObjectId),
+    /// The scene object would become its own ancestor.
+    ParentCycle(SceneObjectId),
+}
+
+impl Error {
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::Io(error) => error.kind() == io::ErrorKind::NotFound,
+            Error::FileFormat(error) => error.is_not_found(),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => error.fmt(f),
+            Error::FileFormat(error) => error.fmt(f),
+            Error::Import(error) => error.fmt(f),
+            Error::AssetNotFound(id) => write!(f, "asset: '{}' doesn't exist", id),
+            Error::AssetUnreadable(id) => write!(f, "asset: '{}' couldn't be read", id),
+            Error::AssetsNotSaved(name) => write!(
+                f,
+                "assets of type: '{}' weren't saved, its manifest couldn't be read",
+                name
+            ),
+            Error::SceneObjectNotFound(id) => write!(f, "scene object: '{}' doesn't exist", id),
+            Error::ParentCycle(id) => {
+                write!(f, "scene object: '{}' can't be moved under itself", id)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<FileFormatError> for Error {
+    fn from(error: FileFormatError) -> Self {
+        Error::FileFormat(error)
+    }
+}
+
+impl From<ImportError> for Error {
+    fn from(error: ImportError) -> Self {
+        Error::Import(error)
+    }
+}
+
+/// Errors reported by systems and editor actions instead of aborting, shown by the editor until
+/// they are dismissed. Resource of every app with the scene or asset server plugin.
+#[derive(Default)]
+pub struct ErrorLog {
+    errors: Vec<String>,
+}
+
+impl ErrorLog {
+    /// Prints the error too, so it isn't lost in apps without the editor.
+    pub fn report<E>(&mut self, action: &str, error: E)
+    where
+        E: Display,
+    {
+        let message = format!("{}: {}", action, error);
+        println!("{}", message);
+
+        self.errors.push(message);
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn dismiss(&mut self, index: usize) {
+        self.errors.remove(index);
+    }
+
+    pub fn clear(&mut self) {
+        self.errors.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs, io,
+    path::Path,
+};
+
+/// Converts the payload of a file from one version of a format to the next one.
+pub type Migration = fn(Vec<u8>) -> Result<Vec<u8>, FileFormatError>;
+
+/// Layout of a file written by [`write`]: a magic number identifying the format, the format
+/// version and the lz4 compressed bincode payload.
+#[derive(Clone, Copy)]
+pub struct FileFormat {
+    pub magic: [u8; 4],
+    /// Migration `i` converts a payload of version `i` to version `i + 1`, so the current version
+    /// is the number of migrations. Files without a header are read as version 0, formats that
+    /// were written before the header existed start with [`headerless`].
+    pub migrations: &'static [Migration],
+}
+
+impl FileFormat {
+    pub fn version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+}
+
+/// Migrates files written before the header existed, their payload didn't change.
+pub fn headerless(payload: Vec<u8>) -> Result<Vec<u8>, FileFormatError> {
+    Ok(payload)
+}
+
+#[derive(Debug)]
+pub enum FileFormatError {
+    Io(io::Error),
+    /// Doesn't start with the magic number of the format and isn't a headerless file either.
+    UnknownFormat,
+    /// Written by a newer version of the game.
+    UnsupportedVersion {
+        version: u32,
+        supported_version: u32,
+    },
+    Corrupt(String),
+}
+
+impl Display for FileFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileFormatError::Io(error) => error.fmt(f),
+            FileFormatError::UnknownFormat => f.write_str("unknown file format"),
+            FileFormatError::UnsupportedVersion {
+                version,
+                supported_version,
+            } => write!(
+                f,
+                "file format version {} is newer than the supported version {}",
+                version, supported_version
+            ),
+            FileFormatError::Corrupt(reason) => write!(f, "corrupt file: {}", reason),
+        }
+    }
+}
+
+impl Error for FileFormatError {}
+
+impl From<io::Error> for FileFormatError {
+    fn from(error: io::Error) -> Self {
+        FileFormatError::Io(error)
+    }
+}
+
+impl FileFormatError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, FileFormatError::Io(error) if error.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+pub fn write<T, P>(path: &P, format: &FileFormat, value: &T) -> io::Result<()>
+where
+    T: Serialize,
+    P: AsRef<Path>,
+{
+    let payload = bincode::serialize::<T>(value).unwrap();
+
+    let mut bytes = Vec::with_capacity(8 + payload.len());
+    bytes.extend_from_slice(&format.magic);
+    bytes.extend_from_slice(&format.version().to_le_bytes());
+    bytes.extend_from_slice(&lz4_flex::compress_prepend_size(&payload));
+
+    fs::write(path, bytes)
+}
+
+pub fn read<T, P>(path: &P, format: &FileFormat) -> Result<T, FileFormatError>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let bytes = fs::read(path)?;
+
+    match bytes.strip_prefix(&format.magic) {
+        Some(bytes) => {
+            if bytes.len() < 4 {
+                return Err(FileFormatError::Corrupt(String::from("truncated header")));
+            }
+
+            let (version, compressed_payload) = bytes.split_at(4);
+            let version = u32::from_le_bytes(version.try_into().unwrap());
+
+            read_payload(version, compressed_payload, format)
+        }
+        // Anything that fails to read without a header most likely isn't a file of this format
+        None => read_payload(0, &bytes, format).map_err(|_| FileFormatError::UnknownFormat),
+    }
+}
+
+fn read_payload<T>(
+    version: u32,
+    compressed_payload: &[u8],
+    format: &FileFormat,
+) -> Result<T, FileFormatError>
+where
+    T: DeserializeOwned,
+{
+    if version > format.version() {
+        return Err(FileFormatError::UnsupportedVersion {
+            version,
+            supported_version: format.version(),
+        });
+    }
+
+    let mut payload = lz4_flex::decompress_size_prepended(compressed_payload)
+        .map_err(|error| FileFormatError::Corrupt(error.to_string()))?;
+
+    for migration in &format.migrations[version as usize..] {
+        payload = migration(payload)?;
+    }
+
+    bincode::deserialize::<T>(&payload).map_err(|error| FileFormatError::Corrupt(error.to_string()))
+}
@@ -1,21 +1,27 @@
-use std::mem;
+use std::{collections::HashSet, mem};
 
 use glam::{Mat4, Vec3, Vec4};
 use wgpu::util::DeviceExt;
 
-pub mod opaque_pass;
-pub mod shadow_pass;
-pub mod z_pre_pass;
+pub mod opaque_render_pass;
+pub mod shadow_render_pass;
+pub mod z_pre_render_pass;
 
 use crate::{
-    app::{Res, ResMut},
-    asset_server::AssetServer,
-    rendering::{light::RenderLight, Renderer, MAX_LIGHTS_COUNT},
+    app::{events::EventReader, App, Plugin, Res, ResMut, Stage},
+    asset_server::{jobs::AssetJobs, AssetImported, AssetRemoved, AssetServer},
+    editor,
+    rendering::{
+        light::{Lights, RenderLight},
+        Renderer, MAX_LIGHTS_COUNT,
+    },
     scene::{scene_object::SceneObject, Scene},
+    ui,
 };
 
 use self::{
-    opaque_pass::OpaqueRenderPass, shadow_pass::ShadowRenderPass, z_pre_pass::ZPreRenderPass,
+    opaque_render_pass::OpaqueRenderPass, shadow_render_pass::ShadowRenderPass,
+    z_pre_render_pass::ZPreRenderPass,
 };
 
 #[repr(C)]
@@ -39,6 +45,33 @@ impl CameraGpu {
     }
 }
 
+/// Game render passes. Requires the rendering plugin.
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        let game = {
+            let renderer = app
+                .get_resource_mut::<Renderer>()
+                .expect("game plugin requires a renderer");
+            let lights = app
+                .get_resource::<Lights>()
+                .expect("game plugin requires lights");
+            let mut renderer = renderer.get_mut();
+            let lights = lights.get();
+
+            Game::new(&mut renderer, &lights)
+        };
+
+        app.add_resource(game);
+        app.add_system(Stage::Update, update).after(editor::LABEL);
+
+        app.add_system(Stage::RenderStart, z_pre_render_pass::render);
+        // app.add_system(Stage::RenderStart, shadow_render_pass::render);
+        app.add_system(Stage::Render, opaque_render_pass::render).before(ui::LABEL);
+    }
+}
+
 pub struct Game {
     // Move this to high level renderer
     pub lights_storage_buffer: wgpu::Buffer,
@@ -125,14 +158,34 @@ pub fn update(
     scene: Res<Scene>,
     renderer: ResMut<Renderer>,
     asset_server: Res<AssetServer>,
+    asset_imported: EventReader<AssetImported>,
+    asset_removed: EventReader<AssetRemoved>,
+    asset_jobs: ResMut<AssetJobs>,
 ) {
     let asset_server = asset_server.get();
+    let mut asset_jobs = asset_jobs.get_mut();
     let mut app = game.get_mut();
     let mut renderer = renderer.get_mut();
     let scene = scene.get();
 
-    renderer.create_render_meshes(&asset_server);
-    renderer.create_render_materials(&asset_server);
+    let changed_ids = asset_imported
+        .get()
+        .iter()
+        .map(|asset_imported| asset_imported.id)
+        .chain(
+            asset_removed
+                .get()
+                .iter()
+                .map(|asset_removed| asset_removed.id),
+        )
+        .collect::<HashSet<_>>();
+    if !changed_ids.is_empty() {
+        renderer.remove_stale_render_assets(&changed_ids, &asset_server);
+    }
+
+    renderer.create_render_meshes(&asset_server, &mut asset_jobs);
+    renderer.create_render_materials(&asset_server, &mut asset_jobs);
+    renderer.read_missing_models(&asset_server, &mut asset_jobs);
 
     if let Some(camera_scene_object) = scene.get(scene.camera_scene_object_id) {
         app.camera_uniform.update(&camera_scene_object);
@@ -2,8 +2,8 @@ use crate::{
     app::{Res, ResMut},
     asset_server::{asset_id::AssetId, AssetServer},
     game::Game,
-    rendering::{self, RenderInstance, Renderer, RenderingRecorder, model::Vertex},
-    Scene,
+    rendering::{self, RenderInstance, Renderer, RenderingRecorder, model::{Model, Vertex}},
+    scene::Scene,
 };
 
 pub struct OpaqueRenderPass {
@@ -227,16 +227,19 @@ pub fn render(
     render_pass.set_bind_group(1, &app.opaque_pass.bind_group, &[]);
     render_pass.set_vertex_buffer(1, renderer.scene_object_instances.slice(..));
 
-    let models = asset_server.models();
+    let models = asset_server.assets::<Model>();
 
-    for (index, scene_object) in scene.scene_objects.iter().enumerate() {
+    for (index, scene_object) in scene.scene_objects.iter_instances() {
         if let Some(model_component) = &scene_object.model_component {
             if model_component.model_id == AssetId::EMPTY {
                 continue;
             }
 
-            let model = models.get(&model_component.model_id).unwrap();
-            
+            // The model may have been removed while still placed in the scene, or not be read yet
+            let Some(model) = renderer.get_model(&models, &model_component.model_id) else {
+                continue;
+            };
+
             for (mesh_id, material_id) in model.mesh_ids.iter().zip(model.material_ids.iter()) {
                 let render_mesh = renderer.get_render_mesh(mesh_id);
                 let render_material = renderer.get_render_material(material_id);
@@ -3,7 +3,7 @@ use crate::{
     asset_server::{AssetServer, asset_id::AssetId},
     game::Game,
     rendering::{self, RenderInstance, Renderer, RenderingRecorder, model::Vertex},
-    Scene,
+    scene::Scene,
 };
 
 pub const SHADOW_PASS_TEXTURE_SIZE: u32 = 2048;
@@ -183,9 +183,9 @@ pub fn render(
     // render_pass.set_bind_group(0, &game.shadow_pass.bind_group, &[]);
     // render_pass.set_vertex_buffer(1, renderer.scene_object_instances.slice(..));
 
-    // let models = asset_server.models();
+    // let models = asset_server.assets::<Model>();
 
-    // for (index, scene_object) in scene.scene_objects.iter().enumerate() {
+    // for (index, scene_object) in scene.scene_objects.iter_instances() {
     //     if let Some(model_component) = &scene_object.model_component {
     //         if model_component.model_id == AssetId::EMPTY {
     //             continue;
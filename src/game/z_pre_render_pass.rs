@@ -1,4 +1,4 @@
-use crate::{app::{Res, ResMut}, rendering::{Renderer, model::Vertex, RenderInstance, self, RenderingRecorder},game::Game, asset_server::{AssetServer, asset_id::AssetId}, scene::Scene};
+use crate::{app::{Res, ResMut}, rendering::{Renderer, model::{Model, Vertex}, RenderInstance, self, RenderingRecorder},game::Game, asset_server::{AssetServer, asset_id::AssetId}, scene::Scene};
 
 
 pub struct ZPreRenderPass {
@@ -120,15 +120,18 @@ pub fn render(
     render_pass.set_bind_group(0, &game.global_bind_group, &[]);
     render_pass.set_vertex_buffer(1, renderer.scene_object_instances.slice(..));
 
-    let models = asset_server.models();
+    let models = asset_server.assets::<Model>();
 
-    for (index, scene_object) in scene.scene_objects.iter().enumerate() {
+    for (index, scene_object) in scene.scene_objects.iter_instances() {
         if let Some(model_component) = &scene_object.model_component {
             if model_component.model_id == AssetId::EMPTY {
                 continue;
             }
 
-            let model = models.get(&model_component.model_id).unwrap();
+            // The model may have been removed while still placed in the scene, or not be read yet
+            let Some(model) = renderer.get_model(&models, &model_component.model_id) else {
+                continue;
+            };
 
             for mesh_id in &model.mesh_ids {
                 if let Some(render_mesh) = renderer.get_render_mesh(mesh_id) {
@@ -1,58 +1,198 @@
 use ::image::{DynamicImage, RgbImage};
 use glam::{Vec2, Vec3, Vec4, Vec4Swizzles};
-use gltf::{accessor::DataType, buffer, image, texture, Semantic};
-use std::{mem, path::Path};
+use gltf::{accessor::DataType, buffer, image, mesh::Mode, texture, Semantic};
+use serde::{Deserialize, Serialize};
+use std::{
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use crate::{
-    asset_server::{asset_id::AssetId, AssetMetadata, AssetServer},
+    asset_server::{
+        asset_id::AssetId, jobs::JobProgress, AssetMetadata, AssetServer, AssetSource, AssetType,
+    },
     rendering::{
         material::{Material, MaterialProperties},
         model::{Mesh, Model},
         texture::Texture,
     },
+    Id,
 };
 
-//TODO: Remove duplicates
+use super::{texture_asset_id, ImportError, ImportSettings};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GltfImportSettings {
+    /// Reflectance of the imported materials, glTF has no equivalent.
+    pub reflectance: f32,
+}
+
+impl Default for GltfImportSettings {
+    fn default() -> Self {
+        Self { reflectance: 0.5 }
+    }
+}
+
+/// Assets read from a glTF file. Textures are identified by their content, so a texture used by
+/// several materials or files is stored once, and the other assets by their place in the file.
+pub struct GltfImport {
+    path: PathBuf,
+    settings: GltfImportSettings,
+    imported_at: SystemTime,
+    textures: Vec<ImportedAsset<Texture>>,
+    materials: Vec<ImportedAsset<Material>>,
+    meshes: Vec<ImportedAsset<Mesh>>,
+    models: Vec<ImportedAsset<Model>>,
+}
+
+type ImportedAsset<T> = (AssetId<T>, AssetMetadata, T);
+
+impl GltfImport {
+    fn metadata(&self, name: Option<&str>, label: String) -> AssetMetadata {
+        AssetMetadata {
+            name: name.map(str::to_owned),
+            source: Some(AssetSource {
+                path: self.path.clone(),
+                label,
+                import_settings: ImportSettings::Gltf(self.settings.clone()),
+                imported_at: Some(self.imported_at),
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Adds the assets to the asset server, replacing the ones imported from the same file before.
+    /// Returns the ids of all the added or replaced assets.
+    pub fn add_to(self, asset_server: &AssetServer) -> Vec<Id> {
+        let mut imported_ids = vec![];
+
+        // A stored texture with the same id has the same content already
+        let textures = self
+            .textures
+            .into_iter()
+            .filter(|(asset_id, _, _)| !asset_server.assets::<Texture>().contains(asset_id))
+            .collect();
+
+        add_imported_assets(textures, &mut imported_ids, asset_server);
+        add_imported_assets(self.materials, &mut imported_ids, asset_server);
+        add_imported_assets(self.meshes, &mut imported_ids, asset_server);
+        add_imported_assets(self.models, &mut imported_ids, asset_server);
+
+        imported_ids
+    }
+}
+
+fn add_imported_assets<T>(
+    imported_assets: Vec<ImportedAsset<T>>,
+    imported_ids: &mut Vec<Id>,
+    asset_server: &AssetServer,
+) where
+    T: AssetType,
+{
+    let mut store = asset_server.assets_mut::<T>();
+
+    for (asset_id, metadata, asset) in imported_assets {
+        store.add_or_replace(asset_id, asset, metadata);
+        imported_ids.push(asset_id.id());
+    }
+}
+
+fn contains<T>(imported_assets: &[ImportedAsset<T>], asset_id: AssetId<T>) -> bool {
+    imported_assets.iter().any(|(id, _, _)| *id == asset_id)
+}
+
+fn find_by_label<T>(imported_assets: &[ImportedAsset<T>], label: &str) -> Option<AssetId<T>> {
+    imported_assets
+        .iter()
+        .find(|(_, metadata, _)| metadata.source.as_ref().unwrap().label == label)
+        .map(|(asset_id, _, _)| *asset_id)
+}
+
 //TODO: Zeux's mesh optimizer https://github.com/zeux/meshoptimizer (but as a processor step not loader)
-pub fn load<P>(path: &P, asset_server: &AssetServer)
+/// Reads every mesh of the file as a model. Runs on a job thread, see [`super::import`].
+pub fn read<P>(
+    path: &P,
+    settings: &GltfImportSettings,
+    progress: &JobProgress,
+) -> Result<GltfImport, ImportError>
 where
     P: AsRef<Path>,
 {
-    let (gltf, buffers, images) = gltf::import(&path).unwrap();
+    let (gltf, buffers, images) = gltf::import(&path)?;
+
+    let mut import = GltfImport {
+        path: path.as_ref().to_owned(),
+        settings: settings.clone(),
+        imported_at: SystemTime::now(),
+        textures: vec![],
+        materials: vec![],
+        meshes: vec![],
+        models: vec![],
+    };
 
-    let mut models = asset_server.models_mut();
-    let mut meshes = asset_server.meshes_mut();
+    // Decoding the file counts as one step, like every primitive
+    let step_count = 1 + gltf
+        .meshes()
+        .map(|mesh| mesh.primitives().len())
+        .sum::<usize>();
+    let mut steps_done = 1;
+    progress.set(steps_done as f32 / step_count as f32);
 
     for mesh in gltf.meshes() {
-        let model_name = if let Some(mesh_name) = mesh.name() {
-            Some(format!("{}", mesh_name.to_owned()))
-        } else {
-            None
-        };
-
-        let model_asset = models.add(Model::default(), AssetMetadata { name: model_name });
+        let mut model = Model::default();
 
         for primitive in mesh.primitives() {
+            if progress.is_cancelled() {
+                return Err(ImportError::Cancelled);
+            }
+
             let mesh_name = if let Some(mesh_name) = mesh.name() {
                 Some(format!("{}_{}", mesh_name.to_owned(), primitive.index()))
             } else {
                 None
             };
-
-            let mesh = create_primitive(&buffers, &primitive);
-            let mesh_metadata = AssetMetadata { name: mesh_name };
+            let mesh_label = format!("mesh{}/primitive{}", mesh.index(), primitive.index());
+            let mesh_asset_id = AssetId::from_source(&import.path, &mesh_label);
+            let mesh_metadata = import.metadata(mesh_name.as_deref(), mesh_label);
 
             let material_asset_id =
-                get_or_create_material(&primitive.material(), &images, asset_server);
+                get_or_create_material(&primitive.material(), &images, &mut import)?;
+
+            import.meshes.push((
+                mesh_asset_id,
+                mesh_metadata,
+                create_primitive(&buffers, &primitive)?,
+            ));
+
+            model.mesh_ids.push(mesh_asset_id);
+            model.material_ids.push(material_asset_id);
 
-            let mesh_asset = meshes.add(mesh, mesh_metadata);
-            model_asset.asset.mesh_ids.push(mesh_asset.id());
-            model_asset.asset.material_ids.push(material_asset_id);
+            steps_done += 1;
+            progress.set(steps_done as f32 / step_count as f32);
         }
+
+        let model_label = format!("mesh{}", mesh.index());
+        let model_asset_id = AssetId::from_source(&import.path, &model_label);
+        let model_metadata = import.metadata(mesh.name(), model_label);
+        import.models.push((model_asset_id, model_metadata, model));
     }
+
+    Ok(import)
 }
 
-fn create_primitive(buffers: &Vec<buffer::Data>, primitive: &gltf::mesh::Primitive<'_>) -> Mesh {
+fn create_primitive(
+    buffers: &Vec<buffer::Data>,
+    primitive: &gltf::mesh::Primitive<'_>,
+) -> Result<Mesh, ImportError> {
+    if primitive.mode() != Mode::Triangles {
+        return Err(ImportError::Unsupported(format!(
+            "primitive mode: {:?}",
+            primitive.mode()
+        )));
+    }
+
     let mut mesh = Mesh::default();
 
     mesh.positions = primitive
@@ -64,17 +204,42 @@ fn create_primitive(buffers: &Vec<buffer::Data>, primitive: &gltf::mesh::Primiti
                 None
             }
         })
-        .unwrap();
-
-    mesh.indices = match primitive.indices().unwrap().data_type() {
-        DataType::U16 => get_data_from_accessor::<u16>(&buffers, &primitive.indices().unwrap())
-            .into_iter()
-            .map(|index| index as u32)
-            .collect::<Vec<_>>(),
-        DataType::U32 => get_data_from_accessor::<u32>(&buffers, &primitive.indices().unwrap()),
-        _ => todo!(),
+        .transpose()?
+        .ok_or_else(|| ImportError::Unsupported(String::from("primitive without positions")))?;
+
+    // Primitives without indices draw every vertex in order
+    mesh.indices = match primitive.indices() {
+        Some(accessor) => match accessor.data_type() {
+            DataType::U8 => get_data_from_accessor::<u8>(&buffers, &accessor)?
+                .into_iter()
+                .map(|index| index as u32)
+                .collect::<Vec<_>>(),
+            DataType::U16 => get_data_from_accessor::<u16>(&buffers, &accessor)?
+                .into_iter()
+                .map(|index| index as u32)
+                .collect::<Vec<_>>(),
+            DataType::U32 => get_data_from_accessor::<u32>(&buffers, &accessor)?,
+            data_type => {
+                return Err(ImportError::Unsupported(format!(
+                    "index type: {:?}",
+                    data_type
+                )))
+            }
+        },
+        None => (0..mesh.positions.len() as u32).collect(),
     };
 
+    if mesh.indices.len() % 3 != 0
+        || mesh
+            .indices
+            .iter()
+            .any(|index| *index as usize >= mesh.positions.len())
+    {
+        return Err(ImportError::Unsupported(String::from(
+            "indices that don't form triangles",
+        )));
+    }
+
     mesh.uvs = primitive
         .attributes()
         .find_map(|(semantic, accessor)| {
@@ -84,6 +249,7 @@ fn create_primitive(buffers: &Vec<buffer::Data>, primitive: &gltf::mesh::Primiti
                 None
             }
         })
+        .transpose()?
         .unwrap_or(vec![Vec2::ZERO; mesh.positions.len()]);
 
     //TODO: encode normals, tangents and bitangents into qtangents (https://www.yosoygames.com.ar/wp/2018/03/vertex-formats-part-1-compression/)
@@ -96,15 +262,19 @@ fn create_primitive(buffers: &Vec<buffer::Data>, primitive: &gltf::mesh::Primiti
                 None
             }
         })
+        .transpose()?
         .unwrap_or_else(|| create_normals(&mesh));
 
-    let encoded_tangents = primitive.attributes().find_map(|(semantic, accessor)| {
-        if semantic == Semantic::Tangents {
-            Some(get_data_from_accessor::<Vec4>(&buffers, &accessor))
-        } else {
-            None
-        }
-    });
+    let encoded_tangents = primitive
+        .attributes()
+        .find_map(|(semantic, accessor)| {
+            if semantic == Semantic::Tangents {
+                Some(get_data_from_accessor::<Vec4>(&buffers, &accessor))
+            } else {
+                None
+            }
+        })
+        .transpose()?;
 
     if let Some(encoded_tangents) = encoded_tangents {
         mesh.bitangents = mesh
@@ -127,16 +297,13 @@ fn create_primitive(buffers: &Vec<buffer::Data>, primitive: &gltf::mesh::Primiti
         mikktspace::generate_tangents(&mut mesh);
     }
 
-    mesh
+    Ok(mesh)
 }
 
+/// The indices have to form triangles, see [`create_primitive`].
 fn create_normals(mesh: &Mesh) -> Vec<Vec3> {
     let mut normal_hits = vec![0; mesh.positions.len()];
 
-    if mesh.indices.len() % 3 != 0 {
-        panic!("this was made to work with triangles");
-    }
-
     let mut normals = vec![Vec3::ZERO; mesh.positions.len()];
 
     for chunk in mesh.indices.chunks(3) {
@@ -175,52 +342,60 @@ fn create_normals(mesh: &Mesh) -> Vec<Vec3> {
 fn get_or_create_asset_texture(
     texture: texture::Texture<'_>,
     images: &Vec<image::Data>,
-    asset_server: &AssetServer,
-) -> AssetId<Texture> {
-    let index = texture.source().index();
-    let image = images.get(index).unwrap();
+    import: &mut GltfImport,
+) -> Result<AssetId<Texture>, ImportError> {
+    let label = format!("texture{}", texture.index());
+    if let Some(asset_id) = find_by_label(&import.textures, &label) {
+        return Ok(asset_id);
+    }
 
-    let name = if let Some(texture_name) = texture.name() {
-        Some(format!("{}", texture_name.to_owned()))
-    } else {
-        None
+    let index = texture.source().index();
+    let image = images.get(index).ok_or_else(|| {
+        ImportError::Malformed(format!(
+            "texture: {} refers to a missing image",
+            texture.index()
+        ))
+    })?;
+
+    let (wgpu_format, wgpu_bytes) = convert_to_valid_wgpu_format(&image)?;
+    let asset_texture = Texture {
+        width: image.width,
+        height: image.height,
+        format: wgpu_format,
+        bytes: wgpu_bytes,
     };
 
-    let mut textures = asset_server.textures_mut();
-
-    let (wgpu_format, wgpu_bytes) = convert_to_valid_wgpu_format(&image);
+    let asset_id = texture_asset_id(&asset_texture);
+    if contains(&import.textures, asset_id) {
+        return Ok(asset_id);
+    }
 
-    let asset = textures.add(
-        Texture {
-            width: image.width,
-            height: image.height,
-            format: wgpu_format,
-            bytes: wgpu_bytes.clone(),
-        },
-        AssetMetadata { name },
-    );
+    let metadata = import.metadata(texture.name(), label);
+    import.textures.push((asset_id, metadata, asset_texture));
 
-    asset.id()
+    Ok(asset_id)
 }
 
 fn get_or_create_material(
     material: &gltf::material::Material,
     images: &Vec<image::Data>,
-    asset_server: &AssetServer,
-) -> AssetId<Material> {
-    let name = if let Some(material_name) = material.name() {
-        Some(format!("{}", material_name.to_owned()))
-    } else {
-        None
+    import: &mut GltfImport,
+) -> Result<AssetId<Material>, ImportError> {
+    // The default material of primitives without one has no index
+    let label = match material.index() {
+        Some(index) => format!("material{}", index),
+        None => String::from("material_default"),
     };
+    let asset_id = AssetId::from_source(&import.path, &label);
+    if contains(&import.materials, asset_id) {
+        return Ok(asset_id);
+    }
+
+    let metadata = import.metadata(material.name(), label);
 
     let color_texture_id =
         if let Some(info) = material.pbr_metallic_roughness().base_color_texture() {
-            Some(get_or_create_asset_texture(
-                info.texture(),
-                images,
-                asset_server,
-            ))
+            Some(get_or_create_asset_texture(info.texture(), images, import)?)
         } else {
             None
         };
@@ -229,8 +404,8 @@ fn get_or_create_material(
         Some(get_or_create_asset_texture(
             normal_texture.texture(),
             images,
-            asset_server,
-        ))
+            import,
+        )?)
     } else {
         None
     };
@@ -241,17 +416,14 @@ fn get_or_create_material(
     let roughness_factor = pbr.roughness_factor();
 
     let metallic_roughness_texture_id = if let Some(info) = pbr.metallic_roughness_texture() {
-        Some(get_or_create_asset_texture(
-            info.texture(),
-            images,
-            asset_server,
-        ))
+        Some(get_or_create_asset_texture(info.texture(), images, import)?)
     } else {
         None
     };
 
-    let mut materials = asset_server.materials_mut();
-    let asset = materials.add(
+    import.materials.push((
+        asset_id,
+        metadata,
         Material {
             color_texture_id,
             normal_texture_id,
@@ -260,63 +432,80 @@ fn get_or_create_material(
                 base_color_factor,
                 metallic_factor,
                 roughness_factor,
-                reflectance: 0.5,
+                reflectance: import.settings.reflectance,
                 padding0: 0.0,
             },
         },
-        AssetMetadata { name },
-    );
+    ));
 
-    asset.id()
+    Ok(asset_id)
 }
 
-fn get_data_from_accessor<T>(buffers: &Vec<buffer::Data>, accessor: &gltf::Accessor) -> Vec<T> {
-    let view = accessor.view().unwrap();
-    let buffer = view.buffer();
+/// Copies the elements out of the buffer, checking the accessor against the buffer and `T` first
+/// as the file may be malformed.
+fn get_data_from_accessor<T>(
+    buffers: &Vec<buffer::Data>,
+    accessor: &gltf::Accessor,
+) -> Result<Vec<T>, ImportError>
+where
+    T: bytemuck::AnyBitPattern,
+{
+    let view = accessor
+        .view()
+        .ok_or_else(|| ImportError::Unsupported(String::from("sparse accessor without a view")))?;
+    let malformed = |problem: &str| {
+        ImportError::Malformed(format!("accessor: {} {}", accessor.index(), problem))
+    };
 
-    let buffer_data = buffers.get(buffer.index()).unwrap();
+    let size = accessor.size();
+    let stride = view.stride().unwrap_or(size);
 
-    let from = view.offset() + accessor.offset();
-    let to = view.offset() + accessor.offset() + accessor.count() * accessor.size();
+    if size != mem::size_of::<T>() || stride < size {
+        return Err(malformed("has the wrong element size"));
+    }
 
-    let view_data = &buffer_data[from..to];
+    let buffer_data = buffers
+        .get(view.buffer().index())
+        .ok_or_else(|| malformed("refers to a missing buffer"))?;
 
-    let data = match view.stride() {
-        Some(stride) => view_data
-            .iter()
-            .enumerate()
-            .filter_map(|(index, byte)| {
-                if index % stride < accessor.size() {
-                    Some(*byte)
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<u8>>(),
-        None => view_data.to_vec(),
-    };
+    let view_data = accessor_range(accessor, &view, stride)
+        .filter(|range| range.end <= view.offset().saturating_add(view.length()))
+        .and_then(|range| buffer_data.get(range))
+        .ok_or_else(|| malformed("reads past the end of its buffer view"))?;
 
-    transmute_byte_vec::<T>(data)
+    Ok(view_data
+        .chunks(stride)
+        .map(|element| bytemuck::pod_read_unaligned(&element[..size]))
+        .collect())
 }
 
-fn transmute_byte_vec<T>(mut bytes: Vec<u8>) -> Vec<T> {
-    unsafe {
-        let size_of_t = mem::size_of::<T>();
-        let length = bytes.len() / size_of_t;
-        let capacity = bytes.capacity() / size_of_t;
-        let mutptr = bytes.as_mut_ptr() as *mut T;
-        mem::forget(bytes);
+/// Bytes of the buffer the accessor covers, the last element isn't followed by the padding of the
+/// stride. `None` if the range doesn't fit in an `usize`.
+fn accessor_range(
+    accessor: &gltf::Accessor,
+    view: &buffer::View,
+    stride: usize,
+) -> Option<Range<usize>> {
+    let from = view.offset().checked_add(accessor.offset())?;
+    let length = match accessor.count() {
+        0 => 0,
+        count => (count - 1)
+            .checked_mul(stride)?
+            .checked_add(accessor.size())?,
+    };
 
-        Vec::from_raw_parts(mutptr, length, capacity)
-    }
+    Some(from..from.checked_add(length)?)
 }
 
-fn convert_to_valid_wgpu_format(image: &image::Data) -> (wgpu::TextureFormat, Vec<u8>) {
-    match image.format {
+fn convert_to_valid_wgpu_format(
+    image: &image::Data,
+) -> Result<(wgpu::TextureFormat, Vec<u8>), ImportError> {
+    let converted = match image.format {
         image::Format::R8 => (wgpu::TextureFormat::R8Unorm, image.pixels.clone()),
         image::Format::R8G8 => (wgpu::TextureFormat::Rg8Unorm, image.pixels.clone()),
         image::Format::R8G8B8 => {
-            let rgb = RgbImage::from_vec(image.width, image.height, image.pixels.clone()).unwrap();
+            let rgb = RgbImage::from_vec(image.width, image.height, image.pixels.clone())
+                .ok_or_else(|| pixel_count_mismatch(image))?;
 
             (
                 wgpu::TextureFormat::Rgba8Unorm,
@@ -326,13 +515,35 @@ fn convert_to_valid_wgpu_format(image: &image::Data) -> (wgpu::TextureFormat, Ve
         image::Format::R8G8B8A8 => (wgpu::TextureFormat::Rgba8Unorm, image.pixels.clone()),
         image::Format::R16 => (wgpu::TextureFormat::R16Unorm, image.pixels.clone()),
         image::Format::R16G16 => (wgpu::TextureFormat::Rg16Unorm, image.pixels.clone()),
-        image::Format::R16G16B16 => unimplemented!(),
+        image::Format::R16G16B16 | image::Format::R32G32B32FLOAT => {
+            return Err(ImportError::Unsupported(format!(
+                "image format: {:?}",
+                image.format
+            )))
+        }
         image::Format::R16G16B16A16 => (wgpu::TextureFormat::Rgba16Unorm, image.pixels.clone()),
-        image::Format::R32G32B32FLOAT => unimplemented!(),
         image::Format::R32G32B32A32FLOAT => {
             (wgpu::TextureFormat::Rgba32Float, image.pixels.clone())
         }
+    };
+
+    let (format, bytes) = &converted;
+    let texel_size = format.block_size(None).unwrap() as u64;
+
+    if bytes.len() as u64 != image.width as u64 * image.height as u64 * texel_size {
+        return Err(pixel_count_mismatch(image));
     }
+
+    Ok(converted)
+}
+
+fn pixel_count_mismatch(image: &image::Data) -> ImportError {
+    ImportError::Malformed(format!(
+        "image of: {}x{} pixels has: {} bytes",
+        image.width,
+        image.height,
+        image.pixels.len()
+    ))
 }
 
 impl mikktspace::Geometry for Mesh {
@@ -0,0 +1,117 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    asset_server::{asset_id::AssetId, jobs::JobProgress, AssetMetadata, AssetServer, AssetSource},
+    rendering::texture::Texture,
+    Id,
+};
+
+use super::{texture_asset_id, ImportError, ImportSettings};
+
+/// Texture read from an image file. It's identified by the file, so importing the file again
+/// replaces the texture in place and materials using it keep working.
+pub struct ImageImport {
+    path: PathBuf,
+    imported_at: SystemTime,
+    asset_id: AssetId<Texture>,
+    texture: Texture,
+}
+
+impl ImageImport {
+    /// Adds the texture to the asset server, or replaces the one imported from the file before.
+    /// Isn't added if the same texture is stored already, like one of a glTF file. Returns its id
+    /// if it was added or replaced.
+    pub fn add_to(self, asset_server: &AssetServer) -> Vec<Id> {
+        let mut store = asset_server.assets_mut::<Texture>();
+
+        if !store.contains(&self.asset_id) && store.contains(&texture_asset_id(&self.texture)) {
+            return vec![];
+        }
+
+        let metadata = AssetMetadata {
+            name: self
+                .path
+                .file_stem()
+                .map(|file_stem| file_stem.to_string_lossy().into_owned()),
+            source: Some(AssetSource {
+                path: self.path,
+                label: String::from("image"),
+                import_settings: ImportSettings::Image,
+                imported_at: Some(self.imported_at),
+            }),
+            ..Default::default()
+        };
+
+        store.add_or_replace(self.asset_id, self.texture, metadata);
+
+        vec![self.asset_id.id()]
+    }
+}
+
+/// Reads the image as an RGBA texture. Runs on a job thread, see [`super::import`].
+pub fn read<P>(path: &P, progress: &JobProgress) -> Result<ImageImport, ImportError>
+where
+    P: AsRef<Path>,
+{
+    let image = ::image::open(path)?.into_rgba8();
+
+    let texture = Texture {
+        width: image.width(),
+        height: image.height(),
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        bytes: image.into_raw(),
+    };
+
+    progress.set(1.0);
+
+    Ok(ImageImport {
+        path: path.as_ref().to_owned(),
+        imported_at: SystemTime::now(),
+        asset_id: AssetId::from_source(path.as_ref(), "image"),
+        texture,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reimporting_a_changed_image_replaces_the_texture() {
+        // Unique per run, so concurrent test runs don't share the file
+        let path = std::env::temp_dir().join(format!(
+            "{}_reimporting_a_changed_image_replaces_the_texture.png",
+            std::process::id()
+        ));
+
+        let mut asset_server = AssetServer::default();
+        asset_server.register::<Texture>();
+
+        ::image::RgbaImage::from_pixel(2, 2, ::image::Rgba([255, 0, 0, 255]))
+            .save(&path)
+            .unwrap();
+        let added_ids = read(&path, &JobProgress::default())
+            .unwrap()
+            .add_to(&asset_server);
+
+        ::image::RgbaImage::from_pixel(4, 4, ::image::Rgba([0, 255, 0, 255]))
+            .save(&path)
+            .unwrap();
+        let replaced_ids = read(&path, &JobProgress::default())
+            .unwrap()
+            .add_to(&asset_server);
+
+        std::fs::remove_file(&path).unwrap();
+
+        let textures = asset_server.assets::<Texture>();
+        let texture = textures.get(&AssetId::from_id(added_ids[0])).unwrap();
+
+        assert_eq!(added_ids, replaced_ids);
+        assert_eq!(textures.len(), 1);
+        assert_eq!((texture.width, texture.height), (4, 4));
+        assert_eq!(&texture.bytes[..4], &[0, 255, 0, 255]);
+    }
+}
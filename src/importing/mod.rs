@@ -1 +1,245 @@
-pub mod gltf;
\ No newline at end of file
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    app::{App, Plugin, Res, ResMut, Stage},
+    asset_server::{
+        asset_id::AssetId,
+        jobs::{AssetJobs, JobId, JobOutput, JobProgress},
+        AssetServer,
+    },
+    rendering::texture::Texture,
+    Id,
+};
+
+use self::gltf::GltfImportSettings;
+
+pub mod gltf;
+pub mod image;
+
+const SOURCE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Re-imports assets whose source files changed. Requires the asset server plugin.
+pub struct ImportingPlugin;
+
+impl Plugin for ImportingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_resource(SourceWatcher::default());
+        app.add_system(Stage::Update, watch_sources);
+    }
+}
+
+/// Importer an asset was imported with and its settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ImportSettings {
+    Gltf(GltfImportSettings),
+    Image,
+}
+
+impl ImportSettings {
+    /// Default settings of the importer for the file, chosen by its extension.
+    pub fn from_extension<P>(path: &P) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let extension = path.as_ref().extension()?.to_string_lossy().to_lowercase();
+
+        match extension.as_str() {
+            "gltf" | "glb" => Some(ImportSettings::Gltf(Default::default())),
+            _ if ::image::ImageFormat::from_extension(&extension).is_some() => {
+                Some(ImportSettings::Image)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    Gltf(::gltf::Error),
+    Image(::image::ImageError),
+    /// The file uses a feature the importer can't handle, like a primitive that isn't made of
+    /// triangles.
+    Unsupported(String),
+    /// The file contradicts itself, like an accessor reading past the end of its buffer.
+    Malformed(String),
+    Cancelled,
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(error) => error.fmt(f),
+            ImportError::Gltf(error) => error.fmt(f),
+            ImportError::Image(error) => error.fmt(f),
+            ImportError::Unsupported(feature) => write!(f, "unsupported: {}", feature),
+            ImportError::Malformed(problem) => write!(f, "malformed file: {}", problem),
+            ImportError::Cancelled => f.write_str("import cancelled"),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(error: io::Error) -> Self {
+        ImportError::Io(error)
+    }
+}
+
+impl From<::gltf::Error> for ImportError {
+    fn from(error: ::gltf::Error) -> Self {
+        ImportError::Gltf(error)
+    }
+}
+
+impl From<::image::ImageError> for ImportError {
+    fn from(error: ::image::ImageError) -> Self {
+        ImportError::Image(error)
+    }
+}
+
+/// Imports the file on a job thread, its assets are added to the asset server once the job
+/// finishes. Assets imported from the same file before are replaced and keep their ids.
+pub fn import<P>(path: &P, settings: &ImportSettings, asset_jobs: &mut AssetJobs) -> JobId
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref().to_owned();
+    let settings = settings.clone();
+
+    asset_jobs.spawn(format!("importing {}", path.display()), move |progress| {
+        read(&path, &settings, progress).map_err(|error| error.to_string())
+    })
+}
+
+/// Imports the file on the calling thread, for tools that don't run the app. Returns the ids of
+/// the added or replaced assets.
+pub fn import_blocking<P>(
+    path: &P,
+    settings: &ImportSettings,
+    asset_server: &AssetServer,
+) -> Result<Vec<Id>, ImportError>
+where
+    P: AsRef<Path>,
+{
+    let output = read(path, settings, &JobProgress::default())?;
+
+    Ok(output(asset_server))
+}
+
+fn read<P>(
+    path: &P,
+    settings: &ImportSettings,
+    progress: &JobProgress,
+) -> Result<JobOutput, ImportError>
+where
+    P: AsRef<Path>,
+{
+    let path = source_path(path)?;
+
+    let output: JobOutput = match settings {
+        ImportSettings::Gltf(settings) => {
+            let import = gltf::read(&path, settings, progress)?;
+
+            Box::new(move |asset_server| import.add_to(asset_server))
+        }
+        ImportSettings::Image => {
+            let import = image::read(&path, progress)?;
+
+            Box::new(move |asset_server| import.add_to(asset_server))
+        }
+    };
+
+    Ok(output)
+}
+
+/// Path a source is stored and identified by, see [`AssetId::from_source`]. The same file gives the
+/// same path however it was reached, and it's relative to the working directory if the file is
+/// inside of it so the project can be moved.
+fn source_path<P>(path: &P) -> io::Result<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let path = fs::canonicalize(path)?;
+    let working_directory = fs::canonicalize(env::current_dir()?)?;
+
+    Ok(match path.strip_prefix(&working_directory) {
+        Ok(relative_path) => relative_path.to_owned(),
+        Err(_) => path,
+    })
+}
+
+/// Textures are identified by their content, so a texture used by several files is stored once.
+fn texture_asset_id(texture: &Texture) -> AssetId<Texture> {
+    AssetId::from_content(&[
+        &texture.width.to_le_bytes(),
+        &texture.height.to_le_bytes(),
+        format!("{:?}", texture.format).as_bytes(),
+        &texture.bytes,
+    ])
+}
+
+/// Modification times of the source files assets were imported from.
+#[derive(Default)]
+pub struct SourceWatcher {
+    modified_times: HashMap<PathBuf, SystemTime>,
+    last_poll: Option<Instant>,
+}
+
+/// Polls the source files of the imported assets and re-imports the ones that changed. A file is
+/// compared with the time its assets were imported when it's first seen, so changes made while
+/// the app wasn't running are picked up too.
+pub fn watch_sources(
+    source_watcher: ResMut<SourceWatcher>,
+    asset_server: Res<AssetServer>,
+    asset_jobs: ResMut<AssetJobs>,
+) {
+    let mut source_watcher = source_watcher.get_mut();
+    let asset_server = asset_server.get();
+    let mut asset_jobs = asset_jobs.get_mut();
+
+    if source_watcher
+        .last_poll
+        .is_some_and(|last_poll| last_poll.elapsed() < SOURCE_POLL_INTERVAL)
+    {
+        return;
+    }
+
+    source_watcher.last_poll = Some(Instant::now());
+
+    for (path, source) in asset_server.sources() {
+        // Missing sources keep the assets imported from them
+        let Ok(modified_time) = fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+            continue;
+        };
+
+        let previous_modified_time = source_watcher
+            .modified_times
+            .insert(path.clone(), modified_time);
+
+        let is_changed = match previous_modified_time {
+            Some(previous_modified_time) => previous_modified_time != modified_time,
+            None => source
+                .imported_at
+                .is_some_and(|imported_at| modified_time > imported_at),
+        };
+
+        if is_changed {
+            println!("re-importing changed source: {:?}", &path);
+
+            // A file that is still being written fails to import, it's imported again on its
+            // next change
+            import(&path, &source.import_settings, &mut asset_jobs);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+#![feature(unboxed_closures)]
+#![feature(fn_traits)]
+#![feature(tuple_trait)]
+#![feature(variant_count)]
+#![feature(type_name_of_val)]
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Debug};
+
+pub mod app;
+pub mod asset_server;
+pub mod components;
+pub mod cooking;
+pub mod editor;
+pub mod error;
+pub mod file_format;
+pub mod game;
+pub mod importing;
+pub mod rendering;
+pub mod scene;
+pub mod ui;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Id(u64);
+
+impl Id {
+    pub fn new() -> Self {
+        Id(fastrand::u64(..))
+    }
+
+    pub fn from_u64(val: u64) -> Self {
+        Id(val)
+    }
+
+    pub const EMPTY: Id = Id(u64::MAX);
+}
+
+impl Default for Id {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Id::EMPTY {
+            f.write_str("empty")
+        } else {
+            f.write_str(&self.0.to_string())
+        }
+    }
+}
+
+impl Debug for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Id::EMPTY {
+            f.write_str("empty")
+        } else {
+            f.debug_tuple("Id").field(&self.0).finish()
+        }
+    }
+}
@@ -1,75 +1,62 @@
-#![feature(unboxed_closures)]
-#![feature(fn_traits)]
-#![feature(tuple_trait)]
-#![feature(variant_count)]
-#![feature(type_name_of_val)]
-
-use app::{App, Stage};
-use asset_server::AssetServer;
-use editor::Editor;
-use game::Game;
-use rendering::{light::Lights, Renderer, RenderingRecorder};
-use scene::Scene;
-use serde::{Deserialize, Serialize};
-use std::fmt::{self, Debug};
+use space_game::{
+    app::{events::Events, App, SystemToggles, AUTOSAVE_LABEL},
+    asset_server::AssetServerPlugin,
+    editor::{self, EditorPlugin},
+    error::ErrorLog,
+    game::GamePlugin,
+    importing::ImportingPlugin,
+    rendering::RenderingPlugin,
+    scene::{Scene, ScenePlugin},
+    ui::UiPlugin,
+};
+use std::{env, process};
 
 use winit::{
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
 };
 
-mod app;
-mod asset_server;
-mod components;
-mod editor;
-mod game;
-mod importing;
-mod rendering;
-mod scene;
-mod ui;
-
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct Id(u64);
-
-impl Id {
-    pub fn new() -> Self {
-        Id(fastrand::u64(..))
-    }
-
-    pub fn from_u64(val: u64) -> Self {
-        Id(val)
-    }
-
-    pub const EMPTY: Id = Id(u64::MAX);
-}
+/// Sent when the window is closed or escape is pressed. The event loop exits after the frame in
+/// which systems could read it.
+pub struct ExitRequested;
 
-impl Default for Id {
-    fn default() -> Self {
-        Self::EMPTY
-    }
-}
+fn main() {
+    let mut args = env::args().skip(1);
+
+    match args.next().as_deref() {
+        Some("--headless") => {
+            let mut frames = 1;
+            let mut save = true;
+
+            for arg in args {
+                match arg.as_str() {
+                    "--no-save" => save = false,
+                    frames_arg => {
+                        frames = frames_arg
+                            .parse()
+                            .expect(&format!("invalid headless frame count: '{}'", frames_arg))
+                    }
+                }
+            }
 
-impl fmt::Display for Id {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if *self == Id::EMPTY {
-            f.write_str("empty")
-        } else {
-            f.write_str(&self.0.to_string())
+            return run_headless(frames, save);
         }
-    }
-}
+        // Format of both files is chosen by their extension: `--convert-scene scene.data scene.ron`
+        Some("--convert-scene") => {
+            let (Some(from_path), Some(to_path)) = (args.next(), args.next()) else {
+                panic!("usage: --convert-scene <from path> <to path>");
+            };
+
+            if let Err(error) = Scene::convert_file(&from_path, &to_path) {
+                eprintln!("failed converting scene: '{}': {}", from_path, error);
+                process::exit(1);
+            }
 
-impl Debug for Id {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if *self == Id::EMPTY {
-            f.write_str("empty")
-        } else {
-            f.debug_tuple("Id").field(&self.0).finish()
+            return;
         }
+        _ => {}
     }
-}
 
-fn main() {
     puffin_egui::puffin::set_scopes_on(true);
 
     let event_loop = EventLoop::new();
@@ -80,86 +67,18 @@ fn main() {
         .build(&event_loop)
         .unwrap();
 
-    let mut renderer = Renderer::new(&window);
-    let editor = Editor::new();
-    let asset_server = AssetServer::read_from_file_or_new(&asset_server::DEFAULT_PATH);
-    let scene = Scene::read_from_file_or_new(&scene::DEFAULT_SCENE_PATH);
-
-    let lights = Lights::new(&renderer);
-    let game = Game::new(&mut renderer, &lights);
-
     let mut app = App::default();
 
-    // EGUI
-    app.add_resource(egui::Context::default());
-    app.add_resource(egui_winit::State::new(&window));
-    app.add_resource(egui::FullOutput::default());
-    app.add_resource(egui_wgpu::Renderer::new(
-        &renderer.device,
-        renderer.surface_format,
-        None,
-        1,
-    ));
-    app.add_resource::<Vec<egui::ClippedPrimitive>>(vec![]);
-    app.add_resource(egui_wgpu::renderer::ScreenDescriptor {
-        size_in_pixels: [window.inner_size().width, window.inner_size().height],
-        pixels_per_point: 1.0,
-    });
-    app.add_system(Stage::Update, ui::update);
-    //
-
-    // DOCKING EDITOR
-    // let tree = {
-    //     let mut tree = Tree::new(vec!["hierarchy".to_string()]);
-    //     let [hierarchy, inspector] =
-    //         tree.split_right(NodeIndex::root(), 0.8, vec!["inspector".to_string()]);
-
-    //     let [hierarchy, file_browser] = tree.split_below(
-    //         hierarchy,
-    //         0.6,
-    //         vec!["file browser".to_string(), "profiler".to_string()],
-    //     );
-
-    //     let [hierarchy, scene] = tree.split_right(hierarchy, 0.25, vec!["scene".to_string()]);
-    //     tree.split_right(scene, 0.5, vec!["game".to_string()]);
-    //     tree
-    // };
-    // app.add_resource::<Tree<String>>(tree);
-    // app.add_system(editor::update);
-    //
-
-    // RENDERER
-    app.add_resource(renderer);
-    app.add_system(Stage::Update, rendering::update_scene_object_transforms);
-
-    app.add_resource(lights);
-    app.add_system(Stage::Update, rendering::light::update_lights);
-    //
-
-    // OLD EDITOR
-    app.add_system(Stage::Update, editor::scene_hierarchy::update);
-    app.add_system(Stage::Update, editor::inspector::update);
-    app.add_system(Stage::Update, editor::asset_browser::update);
-    app.add_system(Stage::Update, editor::debugger::update);
-    //
-
-    app.add_resource(game);
-    app.add_system(Stage::Update, game::update);
-
-    app.add_resource(window);
-    app.add_resource(editor);
-    app.add_resource(asset_server);
-    app.add_resource(scene);
-
-    app.add_resource::<Option<RenderingRecorder>>(None);
-    app.add_system(Stage::RenderSetup, rendering::record);
-    app.add_system(Stage::RenderStart, game::z_pre_render_pass::render);
-    // app.add_system(game::shadow_pass::render);
-    app.add_system(Stage::Render, game::opaque_render_pass::render);
-    app.add_system(Stage::Render, ui::render);
-
-    app.add_system(Stage::RenderPresent,rendering::present);
-    app.add_system(Stage::RenderCleanup,ui::post_render);
+    app.add_event::<ExitRequested>();
+    app.add_non_send_resource(window);
+
+    app.add_plugin(RenderingPlugin)
+        .add_plugin(UiPlugin)
+        .add_plugin(AssetServerPlugin)
+        .add_plugin(ImportingPlugin)
+        .add_plugin(ScenePlugin)
+        .add_plugin(GamePlugin)
+        .add_plugin(EditorPlugin);
 
     event_loop.run(move |event, _, control_flow| {
         let window = app.get_resource::<winit::window::Window>().unwrap();
@@ -169,11 +88,18 @@ fn main() {
                 ref event,
                 window_id,
             } if window_id == window.get().id() => {
-                let context = app.get_resource::<egui::Context>().unwrap();
-                let state = app.get_resource_mut::<egui_winit::State>().unwrap();
-                let response = state.get_mut().on_event(&context.get(), event);
+                // Ui is optional, without its plugin every window event goes to the game.
+                let consumed = match (
+                    app.get_resource::<egui::Context>(),
+                    app.get_resource_mut::<egui_winit::State>(),
+                ) {
+                    (Some(context), Some(state)) => {
+                        state.get_mut().on_event(&context.get(), event).consumed
+                    }
+                    _ => false,
+                };
 
-                if !response.consumed {
+                if !consumed {
                     match event {
                         WindowEvent::CloseRequested
                         | WindowEvent::KeyboardInput {
@@ -185,13 +111,26 @@ fn main() {
                                 },
                             ..
                         } => {
-                            // let event_queue = test_app
-                            //     .get_resource_mut::<EventQueue<EventExitGame>>()
-                            //     .unwrap();
-
-                            // event_queue.get_mut().push_event(EventExitGame {});
+                            app.get_resource_mut::<Events<ExitRequested>>()
+                                .unwrap()
+                                .get_mut()
+                                .send(ExitRequested);
+                        }
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(VirtualKeyCode::F1),
+                                    ..
+                                },
+                            ..
+                        } => {
+                            // Game view, hides the editor windows
+                            let system_toggles = app.get_resource_mut::<SystemToggles>().unwrap();
+                            let mut system_toggles = system_toggles.get_mut();
+                            let is_editor_enabled = system_toggles.is_label_enabled(editor::LABEL);
 
-                            *control_flow = ControlFlow::Exit
+                            system_toggles.set_label_enabled(editor::LABEL, !is_editor_enabled);
                         }
                         WindowEvent::Resized(_physical_size) => {
                             // painter.on_window_resized(physical_size.width, physical_size.height)
@@ -209,9 +148,44 @@ fn main() {
                 puffin_egui::puffin::profile_function!();
                 puffin_egui::puffin::GlobalProfiler::lock().new_frame();
 
-                app.execute();
+                app.update();
+
+                let exit_requested = app.get_resource::<Events<ExitRequested>>().unwrap();
+
+                if !exit_requested.get().is_empty() {
+                    *control_flow = ControlFlow::Exit
+                }
+            }
+            Event::LoopDestroyed => {
+                app.shutdown();
             }
             _ => {}
         }
     });
 }
+
+/// Runs the plugins that don't need a window for a number of frames and exits, for importing
+/// assets and validating scenes on machines without a display:
+/// `space_game --headless [frames] [--no-save]`. Validation runs pass `--no-save`, so the scene
+/// and asset files are left as they are instead of being written back on exit. Exits with an error
+/// code if any file couldn't be loaded or saved.
+fn run_headless(frames: u32, save: bool) {
+    let mut app = App::default();
+
+    app.add_event::<ExitRequested>();
+
+    app.add_plugin(AssetServerPlugin).add_plugin(ScenePlugin);
+
+    if !save {
+        app.set_label_enabled(AUTOSAVE_LABEL, false);
+    }
+
+    app.run_headless(frames);
+
+    let error_count = app.get_resource::<ErrorLog>().unwrap().get().errors().len();
+
+    if error_count > 0 {
+        eprintln!("headless run failed with {} errors", error_count);
+        process::exit(1);
+    }
+}
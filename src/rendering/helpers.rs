@@ -33,10 +33,13 @@ impl<T> Clone for Handle<T> {
 
 impl<T> Copy for Handle<T> {}
 
+/// Objects addressed by generational handles. Removed objects leave a free slot that is reused by
+/// the next add, with a new generation so handles to the removed object stay invalid.
 #[derive(Debug)]
 pub struct Pool<T> {
-    objects: Vec<T>,
+    objects: Vec<Option<T>>,
     generations: Vec<usize>,
+    free_indices: Vec<usize>,
 }
 
 impl<T> Default for Pool<T> {
@@ -44,27 +47,45 @@ impl<T> Default for Pool<T> {
         Self {
             objects: Default::default(),
             generations: Default::default(),
+            free_indices: Default::default(),
         }
     }
 }
 
 impl<T> Pool<T> {
     pub fn add(&mut self, object: T) -> Handle<T> {
-        let index = self.objects.len();
-        self.objects.push(object);
-        self.generations.push(0);
+        let index = match self.free_indices.pop() {
+            Some(index) => index,
+            None => {
+                self.objects.push(None);
+                self.generations.push(0);
+                self.objects.len() - 1
+            }
+        };
+
+        self.objects[index] = Some(object);
 
         Handle {
             index,
-            generation: 0,
+            generation: self.generations[index],
             _pd: PhantomData,
         }
     }
 
+    /// Returns `None` if the handle is stale, the object was removed already.
+    pub fn remove(&mut self, handle: &Handle<T>) -> Option<T> {
+        self.get(handle)?;
+
+        self.generations[handle.index] += 1;
+        self.free_indices.push(handle.index);
+
+        self.objects[handle.index].take()
+    }
+
     pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
         if handle.index < self.objects.len() && handle.generation == self.generations[handle.index]
         {
-            Some(&self.objects[handle.index])
+            self.objects[handle.index].as_ref()
         } else {
             None
         }
@@ -73,7 +94,7 @@ impl<T> Pool<T> {
     pub fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> {
         if handle.index < self.objects.len() && handle.generation == self.generations[handle.index]
         {
-            Some(&mut self.objects[handle.index])
+            self.objects[handle.index].as_mut()
         } else {
             None
         }
@@ -1,5 +1,8 @@
 use super::texture::Texture;
-use crate::asset_server::asset_id::AssetId;
+use crate::{
+    asset_server::{asset_id::AssetId, AssetType},
+    Id,
+};
 use glam::Vec4;
 use serde::{Deserialize, Serialize};
 
@@ -15,7 +18,7 @@ pub struct MaterialProperties {
     pub padding0: f32,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Material {
     pub color_texture_id: Option<AssetId<Texture>>,
     pub normal_texture_id: Option<AssetId<Texture>>,
@@ -24,6 +27,22 @@ pub struct Material {
     pub material_properties: MaterialProperties,
 }
 
+impl AssetType for Material {
+    const NAME: &'static str = "material";
+
+    fn dependencies(&self) -> Vec<Id> {
+        [
+            self.color_texture_id,
+            self.normal_texture_id,
+            self.metallic_roughness_texture_id,
+        ]
+        .iter()
+        .flatten()
+        .map(AssetId::id)
+        .collect()
+    }
+}
+
 pub struct RenderMaterial {
     pub bind_group: wgpu::BindGroup,
 }
@@ -1,19 +1,27 @@
 use crate::{
-    app::{Res, ResMut},
-    asset_server::{asset_id::AssetId, AssetServer},
-    scene::{Scene, SceneObjectId},
+    app::{App, Plugin, Res, ResMut, Stage},
+    asset_server::{asset_id::AssetId, jobs::AssetJobs, Asset, AssetServer, AssetStore, AssetType},
+    cooking::pack::{AssetPack, PackedTexture},
+    editor,
+    scene::Scene,
+    Id,
 };
 
 use egui::epaint::ahash::HashMap;
-use glam::Vec4;
-use std::{cell::RefCell, collections::BTreeMap, iter};
+use glam::{Mat4, Vec4};
+use std::{
+    collections::{BTreeMap, HashSet},
+    iter, mem,
+    sync::Mutex,
+};
 use wgpu::util::DeviceExt;
 use winit::window::Window;
 
 use self::{
     helpers::Pool,
+    light::Lights,
     material::{Material, RenderMaterial},
-    model::{Mesh, RenderMesh, Vertex},
+    model::{Mesh, Model, RenderMesh},
     texture::Texture,
 };
 
@@ -38,6 +46,35 @@ pub const SCENE_OBJECT_INSTANCES_BUFFER_SIZE: u64 = 20 * 1024 * 1024; //20MB
 
 pub const MAX_LIGHTS_COUNT: u64 = 100;
 
+/// Creates the renderer for the window resource and drives the frame recording and presenting.
+pub struct RenderingPlugin;
+
+impl Plugin for RenderingPlugin {
+    fn build(&self, app: &mut App) {
+        let renderer = {
+            let window = app
+                .get_resource::<Window>()
+                .expect("rendering plugin requires a window");
+            let window = window.get();
+
+            Renderer::new(&window)
+        };
+        let lights = Lights::new(&renderer);
+
+        app.add_resource(renderer);
+        app.add_system(Stage::Startup, upload_pack);
+        app.add_system(Stage::Update, update_scene_object_transforms)
+            .after(editor::LABEL);
+
+        app.add_resource(lights);
+        app.add_system(Stage::Update, light::update_lights);
+
+        app.add_resource::<Option<RenderingRecorder>>(None);
+        app.add_system(Stage::RenderSetup, record);
+        app.add_system(Stage::RenderPresent, present);
+    }
+}
+
 // Rename this to low level renderer or gpu interface?
 pub struct Renderer<'renderer> {
     pub instance: wgpu::Instance,
@@ -52,10 +89,15 @@ pub struct Renderer<'renderer> {
     pub render_pass_resources: HashMap<&'renderer str, wgpu::Texture>,
 
     pub render_materials: BTreeMap<AssetId<Material>, RenderMaterial>,
-    missing_render_material_ids: RefCell<Vec<AssetId<Material>>>,
+    missing_render_material_ids: Mutex<Vec<AssetId<Material>>>,
 
     pub render_meshes: BTreeMap<AssetId<Mesh>, RenderMesh>,
-    missing_render_mesh_ids: RefCell<Vec<AssetId<Mesh>>>,
+    missing_render_mesh_ids: Mutex<Vec<AssetId<Mesh>>>,
+
+    missing_model_ids: Mutex<Vec<AssetId<Model>>>,
+
+    /// Textures uploaded from the asset pack, they aren't in the asset server.
+    pub packed_textures: BTreeMap<AssetId<Texture>, wgpu::Texture>,
 
     pub scene_object_instances: wgpu::Buffer,
 
@@ -249,10 +291,14 @@ impl<'renderer> Renderer<'renderer> {
             comparison_sampler,
 
             render_materials: Default::default(),
-            missing_render_material_ids: RefCell::new(Vec::new()),
+            missing_render_material_ids: Mutex::new(Vec::new()),
 
             render_meshes: Default::default(),
-            missing_render_mesh_ids: RefCell::new(Vec::new()),
+            missing_render_mesh_ids: Mutex::new(Vec::new()),
+
+            missing_model_ids: Mutex::new(Vec::new()),
+
+            packed_textures: Default::default(),
 
             mesh_buffers: Default::default(),
 
@@ -275,7 +321,8 @@ impl<'renderer> Renderer<'renderer> {
             Some(render_mesh)
         } else {
             self.missing_render_mesh_ids
-                .borrow_mut()
+                .lock()
+                .unwrap()
                 .push(mesh_id.clone());
             None
         }
@@ -290,32 +337,96 @@ impl<'renderer> Renderer<'renderer> {
             Some(render_material)
         } else {
             self.missing_render_material_ids
-                .borrow_mut()
+                .lock()
+                .unwrap()
                 .push(material_id.clone());
             None
         }
     }
 
-    pub fn create_render_meshes(&mut self, asset_server: &AssetServer) {
-        let mut missing_render_mesh_ids = self.missing_render_mesh_ids.borrow_mut();
-        let meshes = asset_server.meshes();
+    /// Models that haven't been read from disk yet are skipped rather than read on the render
+    /// thread, [`Renderer::read_missing_models`] reads them by a job.
+    pub fn get_model<'store>(
+        &self,
+        models: &'store AssetStore<Model>,
+        model_id: &AssetId<Model>,
+    ) -> Option<&'store Asset<Model>> {
+        if !models.is_loaded(model_id) {
+            self.missing_model_ids.lock().unwrap().push(*model_id);
+            return None;
+        }
+
+        models.get(model_id)
+    }
+
+    /// Reads the models skipped by [`Renderer::get_model`] since the last call.
+    pub fn read_missing_models(&mut self, asset_server: &AssetServer, asset_jobs: &mut AssetJobs) {
+        let missing_model_ids = mem::take(self.missing_model_ids.get_mut().unwrap());
+
+        asset_jobs.read(asset_server, &missing_model_ids);
+    }
+
+    /// Drops the render meshes and materials built from the changed or removed assets, including
+    /// materials using a changed texture, and frees their buffers. They are built again the next
+    /// time they are requested.
+    pub fn remove_stale_render_assets(
+        &mut self,
+        changed_ids: &HashSet<Id>,
+        asset_server: &AssetServer,
+    ) {
+        let mesh_buffers = &mut self.mesh_buffers;
+
+        self.render_meshes.retain(|mesh_id, render_mesh| {
+            if !changed_ids.contains(&mesh_id.id()) {
+                return true;
+            }
+
+            for buffer_handle in [
+                render_mesh.vertex_buffer_handle,
+                render_mesh.index_buffer_handle,
+            ] {
+                if let Some(buffer) = mesh_buffers.remove(&buffer_handle) {
+                    buffer.destroy();
+                }
+            }
+
+            false
+        });
+
+        let materials = asset_server.assets::<Material>();
+
+        self.render_materials.retain(|material_id, _| {
+            !changed_ids.contains(&material_id.id())
+                && materials.get(material_id).is_some_and(|material| {
+                    !material
+                        .dependencies()
+                        .iter()
+                        .any(|texture_id| changed_ids.contains(texture_id))
+                })
+        });
+    }
+
+    /// Meshes that haven't been read from disk yet are read by a job, so the frame doesn't wait
+    /// for them. They are created once they are requested again after that.
+    pub fn create_render_meshes(&mut self, asset_server: &AssetServer, asset_jobs: &mut AssetJobs) {
+        let mut missing_render_mesh_ids = self.missing_render_mesh_ids.lock().unwrap();
+        let meshes = asset_server.assets::<Mesh>();
+        let mut unloaded_mesh_ids = vec![];
 
         while missing_render_mesh_ids.len() > 0 {
             let missing_render_mesh_id = missing_render_mesh_ids.pop().unwrap();
-            let mesh = meshes.get(&missing_render_mesh_id).unwrap();
-
-            let mut vertex_data = Vec::with_capacity(mesh.positions.len());
-
-            for i in 0..mesh.positions.len() {
-                vertex_data.push(Vertex {
-                    position: mesh.positions[i],
-                    normal: mesh.normals[i],
-                    tangent: mesh.tangents[i],
-                    bitangent: mesh.bitangents[i],
-                    uv: mesh.uvs[i],
-                });
+
+            if !meshes.is_loaded(&missing_render_mesh_id) {
+                unloaded_mesh_ids.push(missing_render_mesh_id);
+                continue;
             }
 
+            let Some(mesh) = meshes.get(&missing_render_mesh_id) else {
+                continue;
+            };
+
+            let vertex_data = mesh.vertices();
+
             let vertex_buffer_handle = self.mesh_buffers.add(self.device.create_buffer_init(
                 &wgpu::util::BufferInitDescriptor {
                     label: Some("vertex buffer"),
@@ -344,74 +455,70 @@ impl<'renderer> Renderer<'renderer> {
                 },
             );
         }
+
+        drop(meshes);
+        asset_jobs.read(asset_server, &unloaded_mesh_ids);
     }
 
-    pub fn create_render_materials(&mut self, asset_server: &AssetServer) {
-        let mut missing_render_material_ids = self.missing_render_material_ids.borrow_mut();
-        let materials = asset_server.materials();
-        let textures = asset_server.textures();
+    /// Like [`Renderer::create_render_meshes`], materials are created once they and their
+    /// textures are read from disk.
+    pub fn create_render_materials(
+        &mut self,
+        asset_server: &AssetServer,
+        asset_jobs: &mut AssetJobs,
+    ) {
+        let mut missing_render_material_ids = self.missing_render_material_ids.lock().unwrap();
+        let materials = asset_server.assets::<Material>();
+        let textures = asset_server.assets::<Texture>();
+        let mut unloaded_material_ids = vec![];
+        let mut unloaded_texture_ids = vec![];
 
         while missing_render_material_ids.len() > 0 {
             let missing_render_material_ids = missing_render_material_ids.pop().unwrap();
-            let material = materials.get(&missing_render_material_ids).unwrap();
-
-            let color_texture: Texture = {
-                if let Some(texture_id) = material.color_texture_id {
-                    let asset_texture = textures.get(&texture_id).unwrap();
-
-                    asset_texture.asset.clone()
-                } else {
-                    Texture {
-                        width: 1,
-                        height: 1,
-                        format: wgpu::TextureFormat::Rgba8Unorm,
-                        bytes: vec![255, 255, 255, 255],
-                    }
-                }
-            };
 
-            let wgpu_color_texture = self.create_wgpu_texture(&color_texture);
-            let wgpu_color_texture_view =
-                wgpu_color_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-            let normal_texture = {
-                if let Some(texture_id) = material.normal_texture_id {
-                    let asset_texture = textures.get(&texture_id).unwrap();
-
-                    asset_texture.asset.clone()
-                } else {
-                    Texture {
-                        width: 1,
-                        height: 1,
-                        format: wgpu::TextureFormat::Rgba8Unorm,
-                        bytes: vec![127, 127, 255, 255],
-                    }
-                }
-            };
+            if !materials.is_loaded(&missing_render_material_ids) {
+                unloaded_material_ids.push(missing_render_material_ids);
+                continue;
+            }
 
-            let wgpu_normal_texture = self.create_wgpu_texture(&normal_texture);
-            let wgpu_normal_texture_view =
-                wgpu_normal_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-            let metallic_roughness_texture = {
-                if let Some(texture_id) = material.metallic_roughness_texture_id {
-                    let asset_texture = textures.get(&texture_id).unwrap();
-
-                    asset_texture.asset.clone()
-                } else {
-                    Texture {
-                        width: 1,
-                        height: 1,
-                        format: wgpu::TextureFormat::Rgba8Unorm,
-                        bytes: vec![127, 127, 255, 255],
-                    }
-                }
+            let Some(material) = materials.get(&missing_render_material_ids) else {
+                continue;
             };
 
-            let wgpu_metallic_roughness_texture =
-                self.create_wgpu_texture(&metallic_roughness_texture);
-            let wgpu_metallic_roughness_texture_view = wgpu_metallic_roughness_texture
-                .create_view(&wgpu::TextureViewDescriptor::default());
+            let texture_ids = [
+                material.color_texture_id,
+                material.normal_texture_id,
+                material.metallic_roughness_texture_id,
+            ];
+            let unloaded_material_texture_ids = texture_ids
+                .into_iter()
+                .flatten()
+                // Textures that don't exist are replaced by the defaults below
+                .filter(|texture_id| {
+                    textures.contains(texture_id) && !textures.is_loaded(texture_id)
+                })
+                .collect::<Vec<_>>();
+
+            if !unloaded_material_texture_ids.is_empty() {
+                unloaded_texture_ids.extend(unloaded_material_texture_ids);
+                continue;
+            }
+
+            let wgpu_color_texture_view = self.create_material_texture_view(
+                material.color_texture_id,
+                &textures,
+                [255, 255, 255, 255],
+            );
+            let wgpu_normal_texture_view = self.create_material_texture_view(
+                material.normal_texture_id,
+                &textures,
+                [127, 127, 255, 255],
+            );
+            let wgpu_metallic_roughness_texture_view = self.create_material_texture_view(
+                material.metallic_roughness_texture_id,
+                &textures,
+                [127, 127, 255, 255],
+            );
 
             let material_properties_buffer =
                 self.device
@@ -453,6 +560,136 @@ impl<'renderer> Renderer<'renderer> {
             self.render_materials
                 .insert(material.id(), RenderMaterial { bind_group });
         }
+
+        drop(materials);
+        drop(textures);
+        asset_jobs.read(asset_server, &unloaded_material_ids);
+        asset_jobs.read(asset_server, &unloaded_texture_ids);
+    }
+
+    /// Packed textures are used as they were uploaded, the others are created from their asset, or
+    /// from a single texel of the default color if they don't exist.
+    fn create_material_texture_view(
+        &self,
+        texture_id: Option<AssetId<Texture>>,
+        textures: &AssetStore<Texture>,
+        default_texel: [u8; 4],
+    ) -> wgpu::TextureView {
+        if let Some(packed_texture) =
+            texture_id.and_then(|texture_id| self.packed_textures.get(&texture_id))
+        {
+            return packed_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        }
+
+        let texture = {
+            if let Some(asset_texture) = texture_id.and_then(|texture_id| textures.get(&texture_id))
+            {
+                asset_texture.asset.clone()
+            } else {
+                Texture {
+                    width: 1,
+                    height: 1,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    bytes: default_texel.to_vec(),
+                }
+            }
+        };
+
+        self.create_wgpu_texture(&texture)
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Uploads the meshes and textures of the pack straight from its mapping, the textures with
+    /// every mip level.
+    pub fn upload_pack(&mut self, asset_pack: &AssetPack) {
+        for packed_mesh in &asset_pack.index().meshes {
+            let vertices = asset_pack.vertices(packed_mesh);
+            let indices = asset_pack.indices(packed_mesh);
+
+            let vertex_buffer_handle = self.mesh_buffers.add(self.device.create_buffer_init(
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some("vertex buffer"),
+                    contents: bytemuck::cast_slice(vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                },
+            ));
+
+            let index_buffer_handle = self.mesh_buffers.add(self.device.create_buffer_init(
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some("index buffer"),
+                    contents: bytemuck::cast_slice(indices),
+                    usage: wgpu::BufferUsages::INDEX,
+                },
+            ));
+
+            self.render_meshes.insert(
+                packed_mesh.id,
+                RenderMesh {
+                    vertex_buffer_handle,
+                    vertex_offset: 0,
+                    vertex_count: vertices.len(),
+                    index_buffer_handle,
+                    index_offset: 0,
+                    index_count: indices.len(),
+                },
+            );
+        }
+
+        for packed_texture in &asset_pack.index().textures {
+            let wgpu_texture = self.create_packed_wgpu_texture(asset_pack, packed_texture);
+            self.packed_textures.insert(packed_texture.id, wgpu_texture);
+        }
+    }
+
+    fn create_packed_wgpu_texture(
+        &self,
+        asset_pack: &AssetPack,
+        packed_texture: &PackedTexture,
+    ) -> wgpu::Texture {
+        let wgpu_texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("packed texture"),
+            size: wgpu::Extent3d {
+                width: packed_texture.width,
+                height: packed_texture.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: packed_texture.mips.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: packed_texture.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        // Packs only hold uncompressed formats
+        let texel_size = packed_texture.format.block_size(None).unwrap();
+
+        for mip_level in 0..packed_texture.mips.len() {
+            let (width, height) = packed_texture.mip_size(mip_level);
+            let extents = wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            };
+
+            self.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &wgpu_texture,
+                    mip_level: mip_level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                asset_pack.mip(packed_texture, mip_level),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(texel_size * width),
+                    rows_per_image: Some(height),
+                },
+                extents,
+            );
+        }
+
+        wgpu_texture
     }
 
     pub fn create_wgpu_texture(&self, texture: &Texture) -> wgpu::Texture {
@@ -493,37 +730,34 @@ impl<'renderer> Renderer<'renderer> {
     }
 }
 
+/// Cooked builds have an asset pack, its meshes and textures are only uploaded, not added to the
+/// asset server.
+pub fn upload_pack(renderer: ResMut<Renderer>, asset_pack: Option<Res<AssetPack>>) {
+    if let Some(asset_pack) = asset_pack {
+        renderer.get_mut().upload_pack(&asset_pack.get());
+    }
+}
+
 //TODO: Rework this function to go top to bottom. Currently it goes up recursively potentialy
 // going over the same parent multiple times doing unnecessary recalculations.
 pub fn update_scene_object_transforms(scene: Res<Scene>, renderer: Res<Renderer>) {
     let scene = scene.get();
     let renderer = renderer.get();
 
-    let instances = scene
-        .scene_objects
-        .iter()
-        .map(|mut scene_object| {
-            let mut transform = scene_object.transform_component.build_transform_matrix();
+    // Free slots are never drawn, their instances are left as identity
+    let mut instances = vec![Mat4::IDENTITY; scene.scene_objects.slot_count()];
 
-            loop {
-                if scene_object.parent_id != SceneObjectId::EMPTY {
-                    let parent = scene
-                        .scene_objects
-                        .iter()
-                        .find(|so| so.id() == scene_object.parent_id)
-                        .unwrap();
+    for (index, mut scene_object) in scene.scene_objects.iter_instances() {
+        let mut transform = scene_object.transform_component.build_transform_matrix();
 
-                    transform = parent.transform_component.build_transform_matrix() * transform;
+        while let Some(parent) = scene.get(scene_object.parent_id) {
+            transform = parent.transform_component.build_transform_matrix() * transform;
 
-                    scene_object = parent;
-                } else {
-                    break;
-                }
-            }
+            scene_object = parent;
+        }
 
-            transform
-        })
-        .collect::<Vec<_>>();
+        instances[index] = transform;
+    }
 
     renderer.queue.write_buffer(
         &renderer.scene_object_instances,
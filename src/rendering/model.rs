@@ -1,5 +1,8 @@
 use super::{helpers::Handle, material::Material};
-use crate::asset_server::asset_id::AssetId;
+use crate::{
+    asset_server::{asset_id::AssetId, AssetType},
+    Id,
+};
 use glam::{Vec2, Vec3};
 use serde::{Deserialize, Serialize};
 
@@ -23,6 +26,25 @@ pub struct Mesh {
     pub indices: Vec<u32>,
 }
 
+impl Mesh {
+    /// Interleaves the attributes into the layout of the vertex buffers.
+    pub fn vertices(&self) -> Vec<Vertex> {
+        (0..self.positions.len())
+            .map(|i| Vertex {
+                position: self.positions[i],
+                normal: self.normals[i],
+                tangent: self.tangents[i],
+                bitangent: self.bitangents[i],
+                uv: self.uvs[i],
+            })
+            .collect()
+    }
+}
+
+impl AssetType for Mesh {
+    const NAME: &'static str = "mesh";
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct RenderMesh {
     pub vertex_buffer_handle: Handle<wgpu::Buffer>,
@@ -33,8 +55,20 @@ pub struct RenderMesh {
     pub index_count: usize,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Model {
     pub mesh_ids: Vec<AssetId<Mesh>>,
     pub material_ids: Vec<AssetId<Material>>,
 }
+
+impl AssetType for Model {
+    const NAME: &'static str = "model";
+
+    fn dependencies(&self) -> Vec<Id> {
+        self.mesh_ids
+            .iter()
+            .map(AssetId::id)
+            .chain(self.material_ids.iter().map(AssetId::id))
+            .collect()
+    }
+}
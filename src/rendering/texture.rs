@@ -1,3 +1,4 @@
+use crate::asset_server::AssetType;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,3 +19,7 @@ impl Default for Texture {
         }
     }
 }
+
+impl AssetType for Texture {
+    const NAME: &'static str = "texture";
+}
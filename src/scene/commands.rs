@@ -0,0 +1,84 @@
+use crate::{
+    app::{commands::Commands, App},
+    components::Component,
+};
+
+use super::{scene_object::SceneObject, Scene, SceneObjectId};
+
+fn with_scene<F>(app: &mut App, f: F)
+where
+    F: FnOnce(&mut Scene),
+{
+    let scene = app
+        .get_resource_mut::<Scene>()
+        .expect("failed getting scene from world");
+
+    f(&mut scene.get_mut());
+}
+
+/// Scene edits, queued like every other command and applied at the end of the current stage.
+/// Commands targeting a scene object that was despawned in the meantime are ignored.
+impl Commands {
+    /// Queues a new scene object under `parent_id`, the id is valid right away so more commands
+    /// can target the new object before it exists.
+    pub fn spawn(&self, parent_id: SceneObjectId) -> SceneObjectId {
+        let scene_object_id = SceneObjectId::new();
+
+        self.add(move |app| {
+            with_scene(app, |scene| {
+                scene.add_scene_object_with_id(scene_object_id);
+
+                // Stays at the root if the parent was despawned
+                let _ = scene.reparent(scene_object_id, parent_id);
+            })
+        });
+
+        scene_object_id
+    }
+
+    /// Removes the scene object together with all of its children.
+    pub fn despawn(&self, scene_object_id: SceneObjectId) {
+        self.add(move |app| {
+            with_scene(app, |scene| {
+                let _ = scene.remove_scene_object(scene_object_id);
+            })
+        });
+    }
+
+    pub fn reparent(&self, child_id: SceneObjectId, new_parent_id: SceneObjectId) {
+        self.add(move |app| {
+            with_scene(app, |scene| {
+                let _ = scene.reparent(child_id, new_parent_id);
+            })
+        });
+    }
+
+    pub fn add_component<C>(&self, scene_object_id: SceneObjectId, component: C)
+    where
+        C: Component,
+    {
+        self.edit_scene_object(scene_object_id, move |scene_object| {
+            component.insert(scene_object)
+        });
+    }
+
+    pub fn remove_component<C>(&self, scene_object_id: SceneObjectId)
+    where
+        C: Component,
+    {
+        self.edit_scene_object(scene_object_id, C::remove);
+    }
+
+    fn edit_scene_object<F>(&self, scene_object_id: SceneObjectId, f: F)
+    where
+        F: FnOnce(&mut SceneObject) + Send + 'static,
+    {
+        self.add(move |app| {
+            with_scene(app, |scene| {
+                if let Some(scene_object) = scene.get_mut(scene_object_id) {
+                    f(scene_object);
+                }
+            })
+        });
+    }
+}
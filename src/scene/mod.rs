@@ -1,96 +1,275 @@
 use crate::{
+    app::{App, Plugin, Res, ResMut, Stage, SystemToggles, AUTOSAVE_LABEL},
+    asset_server::asset_id::AssetId,
     components::{camera::CameraComponent, light::LightComponent},
+    error::{Error, ErrorLog},
+    file_format::{self, FileFormat, FileFormatError},
     Id,
 };
 use glam::*;
+use ron::ser::PrettyConfig;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::Path};
+use std::{collections::HashSet, fs, path::Path};
 
-use self::scene_object::SceneObject;
+use self::{scene_object::SceneObject, scene_objects::SceneObjects};
 
 pub type SceneObjectId = Id;
 
 pub const DEFAULT_SCENE_PATH: &'static str = "./scene.data";
 
+/// Add a migration here whenever the serialized layout of the scene changes, for example when a
+/// field is added to a component.
+pub const SCENE_FORMAT: FileFormat = FileFormat {
+    magic: *b"SCNE",
+    migrations: &[file_format::headerless],
+};
+
+/// Scenes saved with this extension are written as RON text, so they can be diffed and reviewed.
+/// Every other extension uses the binary [`SCENE_FORMAT`].
+pub const TEXT_SCENE_EXTENSION: &'static str = "ron";
+
+pub mod commands;
 pub mod scene_object;
+pub mod scene_objects;
+
+pub struct ScenePlugin;
+
+impl Plugin for ScenePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_resource(Scene::default());
+        app.init_resource::<ErrorLog>();
+        app.add_system(Stage::Startup, load);
+        app.add_system(Stage::Shutdown, save).label(AUTOSAVE_LABEL);
+    }
+}
+
+/// Keeps a scene file that can't be read next to the default one, or disables autosave if it can't
+/// be moved, so it isn't overwritten. Leaves it in place if autosave is disabled already.
+pub fn load(
+    scene: ResMut<Scene>,
+    error_log: ResMut<ErrorLog>,
+    system_toggles: ResMut<SystemToggles>,
+) {
+    let error = match Scene::read_from_file_or_new(&DEFAULT_SCENE_PATH) {
+        Ok(loaded_scene) => {
+            scene.replace(loaded_scene);
+            return;
+        }
+        Err(error) => error,
+    };
+
+    let mut error_log = error_log.get_mut();
+
+    // The file is only overwritten by autosave
+    if !system_toggles.get().is_label_enabled(AUTOSAVE_LABEL) {
+        error_log.report("failed loading scene", error);
+        return;
+    }
+
+    let backup_path = Path::new(DEFAULT_SCENE_PATH).with_extension("data.bak");
+
+    // Otherwise saving on exit would replace it with the default scene
+    match fs::rename(DEFAULT_SCENE_PATH, &backup_path) {
+        Ok(()) => error_log.report(
+            "failed loading scene",
+            format!(
+                "{}, the file is kept as: '{}'",
+                error,
+                backup_path.display()
+            ),
+        ),
+        Err(rename_error) => {
+            system_toggles
+                .get_mut()
+                .set_label_enabled(AUTOSAVE_LABEL, false);
+
+            error_log.report("failed loading scene", error);
+            error_log.report(
+                &format!(
+                    "failed keeping the scene file as: '{}', autosave is disabled",
+                    backup_path.display()
+                ),
+                rename_error,
+            );
+        }
+    }
+}
+
+pub fn save(scene: Res<Scene>, error_log: ResMut<ErrorLog>) {
+    if let Err(error) = scene.get().write_to_file(&DEFAULT_SCENE_PATH) {
+        error_log.get_mut().report("failed saving scene", error);
+    }
+}
+
+/// A text scene with the [`SCENE_FORMAT`] version it was written with. Text scenes have no
+/// migrations and none of the scene fields have defaults, so a scene missing a field doesn't read.
+/// Scenes written by a newer version are rejected.
+#[derive(Serialize, Deserialize)]
+struct TextScene<S> {
+    version: u32,
+    scene: S,
+}
+
+fn is_text_scene_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == TEXT_SCENE_EXTENSION)
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Scene {
-    pub scene_objects: Vec<SceneObject>,
+    pub scene_objects: SceneObjects,
     pub camera_scene_object_id: SceneObjectId,
     pub sun_scene_object_id: SceneObjectId,
 }
 
 impl Scene {
-    pub fn read_from_file_or_new<P>(path: &P) -> Self
+    /// Reads a text or a binary scene depending on the extension of the file.
+    pub fn read_from_file<P>(path: &P) -> Result<Self, Error>
     where
         P: AsRef<Path>,
     {
-        if let Ok(bytes) = fs::read(path) {
-            let decompressed_bytes = lz4_flex::decompress_size_prepended(&bytes).unwrap();
+        if !is_text_scene_path(path.as_ref()) {
+            return Ok(file_format::read::<Self, _>(path, &SCENE_FORMAT)?);
+        }
+
+        let text = fs::read_to_string(path)?;
+        let text_scene = ron::from_str::<TextScene<Self>>(&text)
+            .map_err(|error| FileFormatError::Corrupt(error.to_string()))?;
+
+        if text_scene.version > SCENE_FORMAT.version() {
+            return Err(Error::FileFormat(FileFormatError::UnsupportedVersion {
+                version: text_scene.version,
+                supported_version: SCENE_FORMAT.version(),
+            }));
+        }
 
-            bincode::deserialize::<Self>(&decompressed_bytes).unwrap()
-        } else {
-            Default::default()
+        Ok(text_scene.scene)
+    }
+
+    /// Creates the default scene if the file doesn't exist.
+    pub fn read_from_file_or_new<P>(path: &P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        match Self::read_from_file(path) {
+            Err(error) if error.is_not_found() => Ok(Default::default()),
+            result => result,
         }
     }
 
-    pub fn write_to_file<P>(&self, path: &P)
+    /// Writes a text or a binary scene depending on the extension of the file.
+    pub fn write_to_file<P>(&self, path: &P) -> Result<(), Error>
     where
         P: AsRef<Path>,
     {
-        let bytes = bincode::serialize::<Self>(&self).unwrap();
+        if !is_text_scene_path(path.as_ref()) {
+            return Ok(file_format::write(path, &SCENE_FORMAT, self)?);
+        }
 
-        let compressed_bytes = lz4_flex::compress_prepend_size(&bytes);
+        let text_scene = TextScene {
+            version: SCENE_FORMAT.version(),
+            scene: self,
+        };
+        let text = ron::ser::to_string_pretty(&text_scene, PrettyConfig::default()).unwrap();
 
-        fs::write(path, compressed_bytes).unwrap();
+        Ok(fs::write(path, text)?)
+    }
+
+    /// Converts between the text and the binary format, chosen by the extensions of the files.
+    pub fn convert_file<P, Q>(from_path: &P, to_path: &Q) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        Self::read_from_file(from_path)?.write_to_file(to_path)
     }
 
     pub fn add_scene_object(&mut self) -> &mut SceneObject {
-        self.scene_objects.push(SceneObject::default());
+        self.scene_objects.insert(SceneObject::default())
+    }
 
-        self.scene_objects.last_mut().unwrap()
+    pub fn add_scene_object_with_id(&mut self, scene_object_id: SceneObjectId) -> &mut SceneObject {
+        self.scene_objects
+            .insert(SceneObject::with_id(scene_object_id))
     }
 
-    pub fn reparent(&mut self, child_id: SceneObjectId, new_parent_id: SceneObjectId) {
-        let child = self.get_mut(child_id).unwrap();
+    /// Moves the scene object under a new parent, or to the root if the parent is
+    /// [`SceneObjectId::EMPTY`]. Fails without changing anything if either of them doesn't exist,
+    /// or if the new parent is the scene object itself or one of its descendants.
+    pub fn reparent(
+        &mut self,
+        child_id: SceneObjectId,
+        new_parent_id: SceneObjectId,
+    ) -> Result<(), Error> {
+        if new_parent_id != SceneObjectId::EMPTY && self.get(new_parent_id).is_none() {
+            return Err(Error::SceneObjectNotFound(new_parent_id));
+        }
+
+        let mut ancestor = self.get(new_parent_id);
+
+        while let Some(scene_object) = ancestor {
+            if scene_object.id() == child_id {
+                return Err(Error::ParentCycle(child_id));
+            }
+
+            ancestor = self.get(scene_object.parent_id);
+        }
+
+        let child = self
+            .get_mut(child_id)
+            .ok_or(Error::SceneObjectNotFound(child_id))?;
         let old_parent_id = child.parent_id;
         child.parent_id = new_parent_id;
 
         if old_parent_id != SceneObjectId::EMPTY {
-            self.remove_child(old_parent_id, child_id)
+            self.remove_child(old_parent_id, child_id);
         }
 
-        if new_parent_id != SceneObjectId::EMPTY {
-            let new_parent = self.get_mut(new_parent_id).unwrap();
+        if let Some(new_parent) = self.get_mut(new_parent_id) {
             new_parent.children.push(child_id);
         }
+
+        Ok(())
     }
 
     fn remove_child(&mut self, parent_id: SceneObjectId, removed_child_id: SceneObjectId) {
-        let parent: &mut SceneObject = self.get_mut(parent_id).unwrap();
-        let child_index = parent
+        let Some(parent) = self.get_mut(parent_id) else {
+            return;
+        };
+
+        if let Some(child_index) = parent
             .children
             .iter()
             .position(|id| removed_child_id == *id)
-            .unwrap();
-        parent.children.swap_remove(child_index);
+        {
+            parent.children.swap_remove(child_index);
+        }
     }
 
     pub fn get(&self, scene_object_id: SceneObjectId) -> Option<&SceneObject> {
-        self.scene_objects
-            .iter()
-            .find(|scene_object| scene_object.id() == scene_object_id)
+        self.scene_objects.get(scene_object_id)
     }
 
     pub fn get_mut(&mut self, scene_object_id: SceneObjectId) -> Option<&mut SceneObject> {
+        self.scene_objects.get_mut(scene_object_id)
+    }
+
+    /// Ids of the assets used by scene objects, the roots when removing unused assets.
+    pub fn asset_references(&self) -> HashSet<Id> {
         self.scene_objects
-            .iter_mut()
-            .find(|scene_object| scene_object.id() == scene_object_id)
+            .iter()
+            .filter_map(|scene_object| scene_object.model_component.as_ref())
+            .map(|model_component| model_component.model_id)
+            .filter(|model_id| *model_id != AssetId::EMPTY)
+            .map(|model_id| model_id.id())
+            .collect()
     }
 
-    pub fn remove_scene_object(&mut self, scene_object_id: SceneObjectId) {
-        let scene_object = self.get(scene_object_id).unwrap();
+    /// Removes the scene object together with all of its children.
+    pub fn remove_scene_object(&mut self, scene_object_id: SceneObjectId) -> Result<(), Error> {
+        let scene_object = self
+            .get(scene_object_id)
+            .ok_or(Error::SceneObjectNotFound(scene_object_id))?;
         let parent_id = scene_object.parent_id;
         let children = scene_object.children.clone();
 
@@ -101,17 +280,15 @@ impl Scene {
 
         // Destroy all the children
         for child_id in children {
-            self.remove_scene_object(child_id);
+            self.remove_scene_object(child_id)?;
         }
 
-        // Get index and remove from scene objects
-        let index = self
-            .scene_objects
-            .iter()
-            .position(|so| so.id() == scene_object_id)
-            .unwrap();
+        // The slot is freed, other scene objects keep theirs
+        self.scene_objects
+            .remove(scene_object_id)
+            .ok_or(Error::SceneObjectNotFound(scene_object_id))?;
 
-        self.scene_objects.swap_remove(index);
+        Ok(())
     }
 }
 
@@ -138,3 +315,33 @@ impl Default for Scene {
         scene
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reparenting_under_a_descendant_fails() {
+        let mut scene = Scene::default();
+        let parent_id = scene.add_scene_object().id();
+        let child_id = scene.add_scene_object().id();
+        let grandchild_id = scene.add_scene_object().id();
+
+        scene.reparent(child_id, parent_id).unwrap();
+        scene.reparent(grandchild_id, child_id).unwrap();
+
+        assert!(matches!(
+            scene.reparent(parent_id, grandchild_id),
+            Err(Error::ParentCycle(id)) if id == parent_id
+        ));
+        assert!(matches!(
+            scene.reparent(parent_id, parent_id),
+            Err(Error::ParentCycle(_))
+        ));
+        assert_eq!(
+            scene.get(parent_id).unwrap().parent_id,
+            SceneObjectId::EMPTY
+        );
+        assert_eq!(scene.get(grandchild_id).unwrap().parent_id, child_id);
+    }
+}
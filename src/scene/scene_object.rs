@@ -19,6 +19,13 @@ pub struct SceneObject {
 }
 
 impl SceneObject {
+    pub fn with_id(id: SceneObjectId) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
+
     pub fn id(&self) -> SceneObjectId {
         self.id
     }
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+
+use super::{scene_object::SceneObject, SceneObjectId};
+
+/// Where a scene object is stored. The index is also the instance of the scene object in
+/// [`crate::rendering::Renderer::scene_object_instances`], it doesn't change until the scene
+/// object is removed. The slot is then reused with the next generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub index: usize,
+    pub generation: usize,
+}
+
+/// Generational slot map of the scene objects, keyed by their ids.
+#[derive(Debug, Default)]
+pub struct SceneObjects {
+    objects: Vec<Option<SceneObject>>,
+    generations: Vec<usize>,
+    free_indices: Vec<usize>,
+    indices: HashMap<SceneObjectId, usize>,
+}
+
+impl SceneObjects {
+    /// Replaces the scene object with the same id in place, if there is one.
+    pub fn insert(&mut self, scene_object: SceneObject) -> &mut SceneObject {
+        let scene_object_id = scene_object.id();
+
+        let index = match self.indices.get(&scene_object_id) {
+            Some(index) => *index,
+            None => {
+                let index = match self.free_indices.pop() {
+                    Some(index) => index,
+                    None => {
+                        self.objects.push(None);
+                        self.generations.push(0);
+                        self.objects.len() - 1
+                    }
+                };
+
+                self.indices.insert(scene_object_id, index);
+                index
+            }
+        };
+
+        self.objects[index].insert(scene_object)
+    }
+
+    pub fn remove(&mut self, scene_object_id: SceneObjectId) -> Option<SceneObject> {
+        let index = self.indices.remove(&scene_object_id)?;
+
+        self.generations[index] += 1;
+        self.free_indices.push(index);
+
+        self.objects[index].take()
+    }
+
+    pub fn get(&self, scene_object_id: SceneObjectId) -> Option<&SceneObject> {
+        let index = *self.indices.get(&scene_object_id)?;
+
+        self.objects[index].as_ref()
+    }
+
+    pub fn get_mut(&mut self, scene_object_id: SceneObjectId) -> Option<&mut SceneObject> {
+        let index = *self.indices.get(&scene_object_id)?;
+
+        self.objects[index].as_mut()
+    }
+
+    pub fn contains(&self, scene_object_id: SceneObjectId) -> bool {
+        self.indices.contains_key(&scene_object_id)
+    }
+
+    pub fn slot(&self, scene_object_id: SceneObjectId) -> Option<Slot> {
+        let index = *self.indices.get(&scene_object_id)?;
+
+        Some(Slot {
+            index,
+            generation: self.generations[index],
+        })
+    }
+
+    /// Returns `None` if the scene object of the slot was removed, even if the slot is used again.
+    pub fn get_by_slot(&self, slot: Slot) -> Option<&SceneObject> {
+        if self.generations.get(slot.index) == Some(&slot.generation) {
+            self.objects[slot.index].as_ref()
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Number of slots including the free ones, the size of the instance buffer in instances.
+    pub fn slot_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Scene objects in the order of their slots.
+    pub fn iter(&self) -> impl Iterator<Item = &SceneObject> {
+        self.objects.iter().flatten()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut SceneObject> {
+        self.objects.iter_mut().flatten()
+    }
+
+    /// Scene objects together with their instance index, see [`Slot`].
+    pub fn iter_instances(&self) -> impl Iterator<Item = (usize, &SceneObject)> {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter_map(|(index, scene_object)| Some((index, scene_object.as_ref()?)))
+    }
+}
+
+/// Serialized as a list, so the slot map doesn't change the scene format. Slots are compacted
+/// when the scene is read again.
+impl Serialize for SceneObjects {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.iter().collect::<Vec<_>>().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SceneObjects {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut scene_objects = SceneObjects::default();
+
+        for scene_object in Vec::<SceneObject>::deserialize(deserializer)? {
+            scene_objects.insert(scene_object);
+        }
+
+        Ok(scene_objects)
+    }
+}
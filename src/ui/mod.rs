@@ -1,9 +1,52 @@
+use crate::app::App;
+use crate::app::Plugin;
 use crate::app::Res;
 use crate::app::ResMut;
+use crate::app::Stage;
+use crate::app::SystemLabel;
 use crate::rendering::Renderer;
 use crate::rendering::RenderingRecorder;
 use winit::window::Window;
 
+/// Label of the egui frame systems. Windows have to be drawn after `update` begins the frame and
+/// the ui is rendered on top of the game passes.
+pub const LABEL: SystemLabel = "ui";
+
+/// Egui integration. Requires the window and the renderer resources to already be added.
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        let window = app
+            .get_resource::<Window>()
+            .expect("ui plugin requires a window");
+        let renderer = app
+            .get_resource::<Renderer>()
+            .expect("ui plugin requires a renderer");
+        let window = window.get();
+        let renderer = renderer.get();
+
+        app.add_resource(egui::Context::default());
+        app.add_non_send_resource(egui_winit::State::new(&*window));
+        app.add_resource(egui::FullOutput::default());
+        app.add_resource(egui_wgpu::Renderer::new(
+            &renderer.device,
+            renderer.surface_format,
+            None,
+            1,
+        ));
+        app.add_resource::<Vec<egui::ClippedPrimitive>>(vec![]);
+        app.add_resource(egui_wgpu::renderer::ScreenDescriptor {
+            size_in_pixels: [window.inner_size().width, window.inner_size().height],
+            pixels_per_point: 1.0,
+        });
+
+        app.add_system(Stage::Update, update).label(LABEL);
+        app.add_system(Stage::Render, render).label(LABEL);
+        app.add_system(Stage::RenderCleanup, post_render);
+    }
+}
+
 pub fn update(window: Res<Window>, state: ResMut<egui_winit::State>, context: Res<egui::Context>) {
     let mut state = state.get_mut();
     let window = window.get();